@@ -1,30 +1,214 @@
-use anubhav_lang::core::Interpreter;
-use anubhav_lang::lang::{Lexer, Parser};
+use anubhav_lang::core::{Interpreter, TraceHook, compile, exit_code};
+use anubhav_lang::lang::{Lexer, Parser, StatementLines, Token};
 use std::env;
 use std::fs;
 
+/// Longest source line --step shows before cutting it short.
+const STEP_PREVIEW_CHARS: usize = 120;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} [--main] [--strict] [--allow-redefine] [--strict-math] [--collect-asserts] [--no-sleep] [--terminators] [--step] [--check] [--parse-only] [--prelude] [--compile] <file.anubhav>"
+    );
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --main            Call the MAIN function after running top-level statements");
+    eprintln!("  --strict          Treat warnings (such as redefining a function) as errors");
+    eprintln!("  --allow-redefine  Let a FUNCTION replace an earlier one without a warning");
+    eprintln!("  --strict-math     Make arithmetic producing infinity or NaN an error");
+    eprintln!("  --collect-asserts Keep running after a failed ASSERT and report all failures");
+    eprintln!("  --no-sleep        Skip the waits of SLEEP and SLEEP_SEC");
+    eprintln!("  --terminators     End each statement at a newline or ;");
+    eprintln!("  --step            Pause before each statement and show the values it uses");
+    eprintln!("  --check           Report every parse error without running the program");
+    eprintln!("  --parse-only      Same as --check");
+    eprintln!("  --prelude         Define FACTORIAL and IS_PRIME before running");
+    eprintln!("  --compile         Run through a flat instruction list (faster loops)");
+    std::process::exit(1);
+}
+
+/// The --step debugger: shows the source line each statement starts on and
+/// the defined names that line mentions on stderr, then waits for a command
+/// before running it. Commands are read from the interpreter's input, so a
+/// program's INPUT and the debugger take turns on the same lines.
+fn step_hook(source: String, lines: StatementLines) -> TraceHook {
+    let mut stepping = true;
+    Box::new(move |statement, interpreter| {
+        if !stepping {
+            return Ok(());
+        }
+        // Statements from an IMPORT have no line in this file
+        let Some(number) = lines.line_of(statement) else {
+            eprintln!("-> (statement from another file)");
+            return Ok(());
+        };
+        let text = source.lines().nth(number - 1).unwrap_or_default().trim();
+        match text.char_indices().nth(STEP_PREVIEW_CHARS) {
+            Some((end, _)) => eprintln!("-> {number}: {}...", &text[..end]),
+            None => eprintln!("-> {number}: {text}"),
+        }
+        let mut lexer = Lexer::new(text.to_string());
+        let mut names = Vec::new();
+        loop {
+            match lexer.next_token() {
+                Token::Identifier(name) => names.push(name),
+                Token::EOF | Token::Invalid(_) => break,
+                _ => {}
+            }
+        }
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        for line in interpreter.mentioned_state(&names) {
+            eprintln!("   {line}");
+        }
+        loop {
+            eprint!("step> ");
+            // Once input runs out there is no one left to ask
+            let Some(command) = interpreter.read_input_line()? else {
+                stepping = false;
+                return Ok(());
+            };
+            match command.trim() {
+                "" | "s" => return Ok(()),
+                "c" => {
+                    stepping = false;
+                    return Ok(());
+                }
+                // Exits here, since a TRY around the statement would catch an error
+                "q" => std::process::exit(0),
+                _ => eprintln!("Commands: Enter or s to step, c to continue, q to quit"),
+            }
+        }
+    })
+}
+
 pub fn run() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
-        eprintln!("Usage: {} <file.anubhav>", args[0]);
-        std::process::exit(1);
+    let mut call_main = false;
+    let mut strict = false;
+    let mut allow_redefine = false;
+    let mut strict_math = false;
+    let mut collect_asserts = false;
+    let mut no_sleep = false;
+    let mut terminators = false;
+    let mut step = false;
+    let mut check = false;
+    let mut prelude = false;
+    let mut compiled = false;
+    let mut filename = None;
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "--main" => call_main = true,
+            "--strict" => strict = true,
+            "--allow-redefine" => allow_redefine = true,
+            "--strict-math" => strict_math = true,
+            "--collect-asserts" => collect_asserts = true,
+            "--no-sleep" => no_sleep = true,
+            "--terminators" => terminators = true,
+            "--step" => step = true,
+            "--check" | "--parse-only" => check = true,
+            "--prelude" => prelude = true,
+            "--compile" => compiled = true,
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option: {arg}");
+                usage(&args[0]);
+            }
+            _ if filename.is_none() => filename = Some(arg.clone()),
+            _ => usage(&args[0]),
+        }
     }
 
-    let filename = &args[1];
-    let content =
-        fs::read_to_string(filename).unwrap_or_else(|_| panic!("Failed to read file: {filename}"));
+    let Some(filename) = filename else {
+        usage(&args[0]);
+    };
+    // Compiled programs run without the statement-by-statement trace
+    if step && compiled {
+        eprintln!("Error: --step cannot be combined with --compile");
+        std::process::exit(1);
+    }
+    let content = match fs::read_to_string(&filename) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error: failed to read file '{filename}': {e}");
+            std::process::exit(1);
+        }
+    };
 
-    let lexer = Lexer::new(content);
+    // --step shows statements by their source line
+    let source = if step { content.clone() } else { String::new() };
+    let mut lexer = Lexer::new(content);
+    lexer.set_terminators(terminators);
     let mut parser = Parser::new(lexer);
 
+    if check {
+        let (_, errors) = parser.parse_all_errors();
+        for warning in parser.warnings() {
+            eprintln!("Warning: {warning}");
+        }
+        for error in &errors {
+            eprintln!("Parse error: {error}");
+        }
+        if !errors.is_empty() || (strict && !parser.warnings().is_empty()) {
+            std::process::exit(1);
+        }
+        println!("No errors found in '{filename}'");
+        return;
+    }
+
     match parser.parse() {
         Ok(statements) => {
+            for warning in parser.warnings() {
+                eprintln!("Warning: {warning}");
+            }
+            if strict && !parser.warnings().is_empty() {
+                eprintln!("Parse error: warnings are errors under --strict");
+                std::process::exit(1);
+            }
+
             let mut interpreter = Interpreter::new();
-            if let Err(e) = interpreter.execute(statements) {
+            interpreter.set_strict(strict);
+            interpreter.set_allow_redefine(allow_redefine);
+            interpreter.set_strict_math(strict_math);
+            interpreter.set_continue_on_assert_fail(collect_asserts);
+            interpreter.set_no_sleep(no_sleep);
+            if step {
+                interpreter.set_trace(Some(step_hook(source, parser.take_lines())));
+            }
+            if prelude && let Err(e) = interpreter.load_prelude() {
                 eprintln!("Runtime error: {e}");
                 std::process::exit(1);
             }
+            let mut result = if compiled {
+                interpreter.run_program(&compile(&statements))
+            } else {
+                interpreter.execute(statements)
+            };
+            if result.is_ok() && call_main {
+                result = interpreter.run_main();
+            }
+            // Exiting skips the interpreter's drop, so write out its output first
+            let flushed = interpreter.flush();
+            let mut code = match result.and(flushed) {
+                Ok(()) => 0,
+                Err(e) => exit_code(&e).unwrap_or_else(|| {
+                    eprintln!("Runtime error: {e}");
+                    1
+                }),
+            };
+            // Failures collected before an error or EXIT are still reported
+            let failures = interpreter.assert_failures();
+            if !failures.is_empty() {
+                eprintln!("{} assertion(s) failed:", failures.len());
+                for failure in failures {
+                    eprintln!("  {failure}");
+                }
+                if code == 0 {
+                    code = 1;
+                }
+            }
+            if code != 0 {
+                std::process::exit(code);
+            }
         }
         Err(e) => {
             eprintln!("Parse error: {e}");
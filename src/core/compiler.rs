@@ -0,0 +1,356 @@
+// Lowers statements to a flat instruction list with explicit jumps
+use super::interpreter::{Interpreter, LoopSignal, loop_signal};
+use crate::lang::Symbol;
+use crate::lang::parser::{Expression, Statement};
+
+/// One step of a compiled program. Jump targets are instruction indices.
+#[derive(Debug, Clone)]
+enum Instr {
+    Store {
+        name: Symbol,
+        value: Expression,
+    },
+    Calculate {
+        name: String,
+        expression: Expression,
+    },
+    Increment(Symbol),
+    Decrement(Symbol),
+    Jump(usize),
+    /// Continues at `target` when `condition` is zero
+    JumpIfFalse {
+        condition: Expression,
+        target: usize,
+    },
+    /// Evaluates a FOR loop's bounds into counter `slot`
+    ForInit {
+        slot: usize,
+        start: Expression,
+        end: Expression,
+        step: Option<Expression>,
+    },
+    /// Stores counter `slot` in `variable`, or continues at `exit` once it is past the end
+    ForTest {
+        slot: usize,
+        variable: Symbol,
+        exit: usize,
+    },
+    /// Advances counter `slot` and continues at its ForTest
+    ForStep {
+        slot: usize,
+        test: usize,
+    },
+    /// Marks the start of a loop body so BREAK and CONTINUE know where to go
+    EnterLoop {
+        label: Option<String>,
+        break_target: usize,
+        continue_target: usize,
+    },
+    ExitLoop,
+    /// Any other statement, run by the tree-walking interpreter
+    Exec(Statement),
+}
+
+/// A program lowered by `compile`, run with `Interpreter::run_program`.
+#[derive(Debug, Clone)]
+pub struct Program {
+    instrs: Vec<Instr>,
+    for_slots: usize,
+}
+
+impl Program {
+    /// Number of instructions.
+    pub fn len(&self) -> usize {
+        self.instrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instrs.is_empty()
+    }
+}
+
+/// Lowers statements into a flat instruction list. STORE, CALCULATE,
+/// INCREMENT, DECREMENT, IF, WHILE and FOR become instructions with explicit
+/// jumps, so loops run without cloning their bodies; everything else is kept
+/// as a statement and run as before.
+pub fn compile(statements: &[Statement]) -> Program {
+    let mut compiler = Compiler {
+        instrs: Vec::new(),
+        for_depth: 0,
+        for_slots: 0,
+    };
+    compiler.block(statements);
+    Program {
+        instrs: compiler.instrs,
+        for_slots: compiler.for_slots,
+    }
+}
+
+struct Compiler {
+    instrs: Vec<Instr>,
+    for_depth: usize, // FOR loops enclosing the statement being compiled
+    for_slots: usize, // Counters needed by the deepest FOR nesting
+}
+
+impl Compiler {
+    fn block(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Emits a jump whose target is filled in later by `patch`.
+    fn placeholder(&mut self, instr: Instr) -> usize {
+        self.instrs.push(instr);
+        self.instrs.len() - 1
+    }
+
+    fn patch(&mut self, at: usize) {
+        let here = self.instrs.len();
+        match &mut self.instrs[at] {
+            Instr::Jump(target)
+            | Instr::JumpIfFalse { target, .. }
+            | Instr::ForTest { exit: target, .. } => *target = here,
+            Instr::EnterLoop { break_target, .. } => *break_target = here,
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Store { name, value } => self.instrs.push(Instr::Store {
+                name: *name,
+                value: value.clone(),
+            }),
+            Statement::Calculate { name, expression } => self.instrs.push(Instr::Calculate {
+                name: name.clone(),
+                expression: expression.clone(),
+            }),
+            Statement::Increment { variable } => self.instrs.push(Instr::Increment(*variable)),
+            Statement::Decrement { variable } => self.instrs.push(Instr::Decrement(*variable)),
+            Statement::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                let to_else = self.placeholder(Instr::JumpIfFalse {
+                    condition: condition.clone(),
+                    target: 0,
+                });
+                self.block(then_body);
+                if let Some(else_body) = else_body {
+                    let to_end = self.placeholder(Instr::Jump(0));
+                    self.patch(to_else);
+                    self.block(else_body);
+                    self.patch(to_end);
+                } else {
+                    self.patch(to_else);
+                }
+            }
+            Statement::While {
+                condition,
+                body,
+                label,
+            } => {
+                let check = self.instrs.len() + 1;
+                let enter = self.placeholder(Instr::EnterLoop {
+                    label: label.clone(),
+                    break_target: 0,
+                    continue_target: check,
+                });
+                let to_exit = self.placeholder(Instr::JumpIfFalse {
+                    condition: condition.clone(),
+                    target: 0,
+                });
+                self.block(body);
+                self.instrs.push(Instr::Jump(check));
+                self.patch(to_exit);
+                self.patch(enter);
+                self.instrs.push(Instr::ExitLoop);
+            }
+            Statement::For {
+                variable,
+                start,
+                end,
+                step,
+                body,
+                label,
+            } => {
+                let slot = self.for_depth;
+                self.for_depth += 1;
+                self.for_slots = self.for_slots.max(self.for_depth);
+
+                self.instrs.push(Instr::ForInit {
+                    slot,
+                    start: start.clone(),
+                    end: end.clone(),
+                    step: step.clone(),
+                });
+                let enter = self.placeholder(Instr::EnterLoop {
+                    label: label.clone(),
+                    break_target: 0,
+                    continue_target: 0,
+                });
+                let test = self.placeholder(Instr::ForTest {
+                    slot,
+                    variable: *variable,
+                    exit: 0,
+                });
+                self.block(body);
+                let step_at = self.instrs.len();
+                self.instrs.push(Instr::ForStep { slot, test });
+                self.patch(test);
+                self.patch(enter);
+                if let Instr::EnterLoop {
+                    continue_target, ..
+                } = &mut self.instrs[enter]
+                {
+                    *continue_target = step_at;
+                }
+                self.instrs.push(Instr::ExitLoop);
+
+                self.for_depth -= 1;
+            }
+            other => self.instrs.push(Instr::Exec(other.clone())),
+        }
+    }
+}
+
+/// A loop entered while running a program.
+struct ActiveLoop<'a> {
+    label: Option<&'a str>,
+    break_target: usize,
+    continue_target: usize,
+}
+
+impl Interpreter {
+    /// Runs a compiled program. Behaves like `execute` on the statements it
+    /// was compiled from.
+    pub fn run_program(&mut self, program: &Program) -> Result<(), String> {
+        // (current, end, step) for each FOR nesting level
+        let mut counters = vec![(0.0, 0.0, 0.0); program.for_slots];
+        let mut loops: Vec<ActiveLoop> = Vec::new();
+        let mut pc = 0;
+
+        while let Some(instr) = program.instrs.get(pc) {
+            pc += 1;
+            let result = match instr {
+                Instr::Store { name, value } => self
+                    .evaluate_expression(value)
+                    .map(|result| self.store_variable(name, result)),
+                Instr::Calculate { name, expression } => self
+                    .evaluate_expression(expression)
+                    .map(|result| self.store_calculation(name.clone(), result)),
+                Instr::Increment(variable) => {
+                    let val = self.number_value(variable).unwrap_or(0.0);
+                    self.store_variable(variable, val + 1.0);
+                    Ok(())
+                }
+                Instr::Decrement(variable) => {
+                    let val = self.number_value(variable).unwrap_or(0.0);
+                    self.store_variable(variable, val - 1.0);
+                    Ok(())
+                }
+                Instr::Jump(target) => {
+                    pc = *target;
+                    Ok(())
+                }
+                Instr::JumpIfFalse { condition, target } => {
+                    self.evaluate_expression(condition).map(|value| {
+                        if value == 0.0 {
+                            pc = *target;
+                        }
+                    })
+                }
+                Instr::ForInit {
+                    slot,
+                    start,
+                    end,
+                    step,
+                } => self.for_bounds(start, end, step.as_ref()).map(|bounds| {
+                    counters[*slot] = bounds;
+                }),
+                Instr::ForTest {
+                    slot,
+                    variable,
+                    exit,
+                } => {
+                    let (current, end, step) = counters[*slot];
+                    let in_range = (step > 0.0 && current <= end) || (step < 0.0 && current >= end);
+                    if in_range {
+                        self.store_variable(variable, current);
+                    } else {
+                        pc = *exit;
+                    }
+                    Ok(())
+                }
+                Instr::ForStep { slot, test } => {
+                    counters[*slot].0 += counters[*slot].2;
+                    pc = *test;
+                    Ok(())
+                }
+                Instr::EnterLoop {
+                    label,
+                    break_target,
+                    continue_target,
+                } => {
+                    loops.push(ActiveLoop {
+                        label: label.as_deref(),
+                        break_target: *break_target,
+                        continue_target: *continue_target,
+                    });
+                    Ok(())
+                }
+                Instr::ExitLoop => {
+                    loops.pop();
+                    Ok(())
+                }
+                Instr::Exec(statement) => self.execute_block(std::slice::from_ref(statement)),
+            };
+
+            // BREAK and CONTINUE arrive as errors; send them to the loop they name
+            if let Err(e) = result {
+                loop {
+                    let Some(active) = loops.last() else {
+                        return Err(e);
+                    };
+                    match loop_signal(&e, active.label) {
+                        LoopSignal::Break => {
+                            pc = active.break_target;
+                            break;
+                        }
+                        LoopSignal::Continue => {
+                            pc = active.continue_target;
+                            break;
+                        }
+                        // A labeled signal for an outer loop leaves this one
+                        LoopSignal::Propagate
+                            if e.starts_with("BREAK@") || e.starts_with("CONTINUE@") =>
+                        {
+                            loops.pop();
+                        }
+                        LoopSignal::Propagate => return Err(e),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates a FOR loop's start, end and step (1 when omitted).
+    fn for_bounds(
+        &mut self,
+        start: &Expression,
+        end: &Expression,
+        step: Option<&Expression>,
+    ) -> Result<(f64, f64, f64), String> {
+        let start = self.evaluate_expression(start)?;
+        let end = self.evaluate_expression(end)?;
+        let step = match step {
+            Some(step) => self.evaluate_expression(step)?,
+            None => 1.0,
+        };
+        Ok((start, end, step))
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Keys;
+use std::ops::Index;
+
+/// A table of arrays or dictionaries by name. While a function runs, each of
+/// its array and dictionary parameters is another name for the caller's
+/// container: lookups through the parameter reach the caller's entry, which
+/// keeps its own name, and a global of the parameter's name is hidden.
+#[derive(Debug)]
+pub(crate) struct ContainerTable<T> {
+    entries: HashMap<String, T>,
+    aliases: Vec<HashMap<String, String>>, // Parameter -> caller's name, one frame per call
+}
+
+impl<T> Default for ContainerTable<T> {
+    fn default() -> Self {
+        ContainerTable {
+            entries: HashMap::new(),
+            aliases: Vec::new(),
+        }
+    }
+}
+
+impl<T> ContainerTable<T> {
+    /// The name `name` stands for in the running function. Only the innermost
+    /// call's parameters count, as with local variables.
+    pub(crate) fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases
+            .last()
+            .and_then(|frame| frame.get(name))
+            .map_or(name, String::as_str)
+    }
+
+    pub(crate) fn push_aliases(&mut self, frame: HashMap<String, String>) {
+        self.aliases.push(frame);
+    }
+
+    pub(crate) fn pop_aliases(&mut self) {
+        self.aliases.pop();
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&T> {
+        self.entries.get(self.resolve(name))
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        let name = match self.aliases.last().and_then(|frame| frame.get(name)) {
+            Some(source) => source,
+            None => name,
+        };
+        self.entries.get_mut(name)
+    }
+
+    pub(crate) fn contains_key(&self, name: &str) -> bool {
+        self.entries.contains_key(self.resolve(name))
+    }
+
+    pub(crate) fn insert(&mut self, name: String, value: T) -> Option<T> {
+        let name = match self.aliases.last().and_then(|frame| frame.get(&name)) {
+            Some(source) => source.clone(),
+            None => name,
+        };
+        self.entries.insert(name, value)
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> Option<T> {
+        let name = match self.aliases.last().and_then(|frame| frame.get(name)) {
+            Some(source) => source,
+            None => name,
+        };
+        self.entries.remove(name)
+    }
+
+    /// The names entries are stored under, without the running function's
+    /// parameters.
+    pub(crate) fn keys(&self) -> Keys<'_, String, T> {
+        self.entries.keys()
+    }
+}
+
+impl<T> Index<&str> for ContainerTable<T> {
+    type Output = T;
+
+    fn index(&self, name: &str) -> &T {
+        &self.entries[self.resolve(name)]
+    }
+}
@@ -1,9 +1,9 @@
 // Extension implementations for the interpreter
-use super::interpreter::Interpreter;
+use super::interpreter::{Interpreter, emit, file_error};
+use crate::lang::interner::Symbol;
 use crate::lang::parser::Statement;
 use std::collections::HashMap;
 use std::fs;
-use std::io::{self, Write};
 use std::thread;
 use std::time::Duration;
 
@@ -12,7 +12,7 @@ impl Interpreter {
         match statement {
             Statement::DictCreate { name } => {
                 self.dicts.insert(name.clone(), HashMap::new());
-                println!("Dictionary '{name}' created");
+                emit!(self, "Dictionary '{name}' created");
                 Ok(())
             }
             Statement::DictPut {
@@ -21,9 +21,10 @@ impl Interpreter {
                 value,
             } => {
                 let val = self.evaluate_expression(&value)?;
+                let key = self.resolve_dict_key(&key)?;
                 if let Some(dict) = self.dicts.get_mut(&dict_name) {
                     dict.insert(key.clone(), val);
-                    println!("Set {dict_name}['{key}'] = {val}");
+                    emit!(self, "Set {dict_name}['{key}'] = {val}");
                 } else {
                     return Err(format!("Dictionary '{dict_name}' not found"));
                 }
@@ -34,14 +35,13 @@ impl Interpreter {
                 key,
                 result_name,
             } => {
+                let key = self.resolve_dict_key(&key)?;
                 if let Some(dict) = self.dicts.get(&dict_name) {
                     if let Some(&value) = dict.get(&key) {
-                        self.variables.insert(result_name.clone(), value);
-                        println!("Fetched {dict_name}['{key}'] = {value}");
+                        self.variables.insert(Symbol::intern(&result_name), value);
+                        emit!(self, "Fetched {dict_name}['{key}'] = {value}");
                     } else {
-                        return Err(format!(
-                            "Key '{key}' not found in dictionary '{dict_name}'"
-                        ));
+                        return Err(format!("Key '{key}' not found in dictionary '{dict_name}'"));
                     }
                 } else {
                     return Err(format!("Dictionary '{dict_name}' not found"));
@@ -55,9 +55,9 @@ impl Interpreter {
                 match fs::read_to_string(&filename) {
                     Ok(content) => {
                         self.intents.insert(result_name.clone(), content.clone());
-                        println!("Read {} bytes from '{}'", content.len(), filename);
+                        emit!(self, "Read {} bytes from '{}'", content.len(), filename);
                     }
-                    Err(e) => return Err(format!("Failed to read file '{filename}': {e}")),
+                    Err(e) => return Err(file_error("read file", &filename, &e)),
                 }
                 Ok(())
             }
@@ -74,35 +74,47 @@ impl Interpreter {
                 };
 
                 match fs::write(&filename, actual_content.as_bytes()) {
-                    Ok(_) => println!("Wrote {} bytes to '{}'", actual_content.len(), filename),
-                    Err(e) => return Err(format!("Failed to write to file '{filename}': {e}")),
+                    Ok(_) => emit!(
+                        self,
+                        "Wrote {} bytes to '{}'",
+                        actual_content.len(),
+                        filename
+                    ),
+                    Err(e) => return Err(file_error("write to file", &filename, &e)),
                 }
                 Ok(())
             }
             Statement::Sleep { milliseconds } => {
                 let ms = self.evaluate_expression(&milliseconds)? as u64;
-                println!("Sleeping for {ms} ms...");
-                thread::sleep(Duration::from_millis(ms));
+                emit!(self, "Sleeping for {ms} ms...");
+                self.flush()?;
+                if !self.no_sleep {
+                    thread::sleep(Duration::from_millis(ms));
+                }
                 Ok(())
             }
             Statement::Input {
                 prompt,
                 result_name,
+                ..
             } => {
-                print!("{prompt}");
-                io::stdout().flush().unwrap();
+                self.write_output(&prompt)?;
+                self.flush()?;
 
                 let mut input = String::new();
-                io::stdin()
+                self.input
                     .read_line(&mut input)
                     .map_err(|e| format!("Failed to read input: {e}"))?;
 
                 let trimmed = input.trim().to_string();
 
-                // Try to parse as number, otherwise store as string
+                // Try to parse as number, otherwise store as string; either
+                // replaces a binding of the other kind
                 if let Ok(num) = trimmed.parse::<f64>() {
-                    self.variables.insert(result_name.clone(), num);
+                    self.intents.remove(&result_name);
+                    self.variables.insert(Symbol::intern(&result_name), num);
                 } else {
+                    self.variables.remove(&Symbol::intern(&result_name));
                     self.intents.insert(result_name.clone(), trimmed);
                 }
 
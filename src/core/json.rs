@@ -0,0 +1,337 @@
+// A small JSON reader and writer for PARSE_JSON and SERIALIZE_JSON
+use super::value::{Value, format_number};
+use std::collections::HashMap;
+
+/// Deepest nesting of arrays and objects accepted, so hostile input cannot
+/// exhaust the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Parses JSON text into a value: objects become records, arrays become
+/// `Value::Array`, `true`/`false` become 1 and 0. A `null` object member is
+/// left out of its record; `null` anywhere else has nothing to become and is
+/// an error. Errors give the character position they were found at.
+pub(crate) fn parse_json(text: &str) -> Result<Value, String> {
+    let mut reader = JsonReader {
+        chars: text.chars().collect(),
+        position: 0,
+        depth: 0,
+    };
+    reader.skip_whitespace();
+    let value = reader.parse_value()?;
+    reader.skip_whitespace();
+    if reader.position < reader.chars.len() {
+        return Err(reader.error("Unexpected text after the JSON value"));
+    }
+    value.ok_or_else(|| "JSON null has no value to store".to_string())
+}
+
+/// Writes a value as compact JSON. Records have no field order, so keys are
+/// written sorted to give the same text on every run; numbers are written as
+/// PRINT writes them.
+pub(crate) fn to_json(value: &Value) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(&mut out, value)?;
+    Ok(out)
+}
+
+fn write_value(out: &mut String, value: &Value) -> Result<(), String> {
+    match value {
+        Value::Number(n) if !n.is_finite() => {
+            return Err(format!("JSON cannot represent the number {n}"));
+        }
+        Value::Number(n) => out.push_str(&format_number(*n)),
+        Value::Text(text) => write_string(out, text),
+        Value::FnRef(name) => {
+            return Err(format!(
+                "JSON cannot represent the function reference to '{name}'"
+            ));
+        }
+        Value::Record(fields) => {
+            let mut names: Vec<&String> = fields.keys().collect();
+            names.sort();
+            out.push('{');
+            for (i, name) in names.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, name);
+                out.push(':');
+                write_value(out, &fields[name])?;
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item)?;
+            }
+            out.push(']');
+        }
+    }
+    Ok(())
+}
+
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct JsonReader {
+    chars: Vec<char>,
+    position: usize,
+    depth: usize,
+}
+
+impl JsonReader {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid JSON at position {}: {message}", self.position)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{expected}'")))
+        }
+    }
+
+    /// Reads one value; `None` is `null`.
+    fn parse_value(&mut self) -> Result<Option<Value>, String> {
+        match self.peek() {
+            Some('{') => self.nested(Self::parse_object).map(Some),
+            Some('[') => self.nested(Self::parse_array).map(Some),
+            Some('"') => Ok(Some(Value::Text(self.parse_string()?))),
+            Some('-' | '0'..='9') => Ok(Some(Value::Number(self.parse_number()?))),
+            Some('t') => self.literal("true", Some(Value::Number(1.0))),
+            Some('f') => self.literal("false", Some(Value::Number(0.0))),
+            Some('n') => self.literal("null", None),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nested more than {MAX_DEPTH} levels deep")));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn literal(&mut self, word: &str, value: Option<Value>) -> Result<Option<Value>, String> {
+        let end = self.position + word.chars().count();
+        if self
+            .chars
+            .get(self.position..end)
+            .is_some_and(|s| s.iter().copied().eq(word.chars()))
+        {
+            self.position = end;
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut fields = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Value::Record(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            // A later duplicate key replaces an earlier one
+            match self.parse_value()? {
+                Some(value) => fields.insert(key, value),
+                None => fields.remove(&key),
+            };
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(Value::Record(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            let start = self.position;
+            match self.parse_value()? {
+                Some(value) => items.push(value),
+                None => {
+                    self.position = start;
+                    return Err(self.error("null is not allowed in an array"));
+                }
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            let Some(ch) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+            match ch {
+                '"' => return Ok(text),
+                '\\' => text.push(self.parse_escape()?),
+                c if (c as u32) < 0x20 => {
+                    self.position -= 1;
+                    return Err(self.error("control character in string"));
+                }
+                c => text.push(c),
+            }
+        }
+    }
+
+    /// Reads the escape after a backslash.
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let Some(ch) = self.peek() else {
+            return Err(self.error("unterminated string"));
+        };
+        self.position += 1;
+        Ok(match ch {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.parse_hex4()?;
+                // A character outside the Basic Multilingual Plane is written
+                // as a surrogate pair, as in `\ud83d\ude00`
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    if self.peek() != Some('\\') || self.chars.get(self.position + 1) != Some(&'u')
+                    {
+                        return Err(self.error("unpaired surrogate in \\u escape"));
+                    }
+                    self.position += 2;
+                    let low = self.parse_hex4()?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(self.error("unpaired surrogate in \\u escape"));
+                    }
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))?
+            }
+            _ => {
+                self.position -= 1;
+                return Err(self.error("invalid escape"));
+            }
+        })
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.iter().skip(self.position).take(4).collect();
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(self.error("expected four hex digits after \\u"));
+        }
+        self.position += 4;
+        Ok(u32::from_str_radix(&digits, 16).expect("checked hex digits"))
+    }
+
+    /// Reads a number in JSON's grammar: no leading `+`, no leading zeros,
+    /// digits on both sides of a point.
+    fn parse_number(&mut self) -> Result<f64, String> {
+        let start = self.position;
+        let digits = |reader: &mut Self| {
+            let from = reader.position;
+            while reader.peek().is_some_and(|c| c.is_ascii_digit()) {
+                reader.position += 1;
+            }
+            reader.position > from
+        };
+
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        if self.peek() == Some('0') {
+            self.position += 1;
+        } else if !digits(self) {
+            return Err(self.error("expected a digit"));
+        }
+        if self.peek() == Some('.') {
+            self.position += 1;
+            if !digits(self) {
+                return Err(self.error("expected a digit after '.'"));
+            }
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.position += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.position += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected a digit in the exponent"));
+            }
+        }
+
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse()
+            .map_err(|_| format!("Invalid JSON at position {start}: bad number"))
+    }
+}
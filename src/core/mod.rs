@@ -1,4 +1,11 @@
+pub mod compiler;
+mod containers;
 pub mod extensions;
 pub mod interpreter;
+pub mod json;
+pub mod prelude;
+pub mod value;
 
-pub use interpreter::Interpreter;
+pub use compiler::{Program, compile};
+pub use interpreter::{Interpreter, TraceHook, exit_code};
+pub use value::Value;
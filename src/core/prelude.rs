@@ -0,0 +1,19 @@
+// Standard functions written in Anubhav itself, registered on demand
+use super::interpreter::Interpreter;
+use crate::lang::{Lexer, Parser};
+
+/// Source of the prelude: FACTORIAL and IS_PRIME, plus the PRELUDE_* helpers
+/// they call. GCD and LCM are built-in expression functions.
+pub const PRELUDE: &str = include_str!("prelude.anubhav");
+
+impl Interpreter {
+    /// Defines the prelude functions so programs can CALL them without
+    /// writing them first. Their definition messages are not printed.
+    pub fn load_prelude(&mut self) -> Result<(), String> {
+        let mut parser = Parser::new(Lexer::new(PRELUDE.to_string()));
+        let statements = parser.parse().map_err(|e| format!("Prelude: {e}"))?;
+
+        self.execute_silently(&statements)
+            .map_err(|e| format!("Prelude: {e}"))
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A value stored in a record field. Records may nest other records, and
+/// arrays of any values (from PARSE_JSON).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    Record(HashMap<String, Value>),
+    FnRef(String),
+    Array(Vec<Value>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::Text(s) => write!(f, "{s}"),
+            Value::FnRef(name) => write!(f, "<function {name}>"),
+            Value::Record(fields) => {
+                // Sort by field name so output is deterministic
+                let mut names: Vec<&String> = fields.keys().collect();
+                names.sort();
+                write!(f, "{{")?;
+                for (i, name) in names.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match &fields[*name] {
+                        Value::Text(s) => write!(f, "{name}: \"{s}\"")?,
+                        other => write!(f, "{name}: {other}")?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        Value::Text(s) => write!(f, "\"{s}\"")?,
+                        other => write!(f, "{other}")?,
+                    }
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// The one place numbers are turned into text for output and files.
+/// Whole numbers print without a fractional part and `-0` prints as `0`.
+pub(crate) fn format_number(n: f64) -> String {
+    if n == 0.0 {
+        "0".to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+/// Formats a number with comma group separators, e.g. `-1234567.5` as `-1,234,567.5`.
+pub(crate) fn format_thousands(n: f64) -> String {
+    let text = format_number(n);
+    if !n.is_finite() {
+        return text;
+    }
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text.as_str()),
+    };
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits, None),
+    };
+    let mut grouped = String::from(sign);
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped.push_str(fraction);
+    }
+    grouped
+}
+
+/// Formats a number in scientific notation with `places` digits after the point,
+/// e.g. `1234567` with 2 places as `1.23e6`.
+pub(crate) fn format_scientific(n: f64, places: usize) -> String {
+    // Adding 0.0 turns -0 into 0
+    format!("{:.places$e}", n + 0.0)
+}
+
+/// Largest width or precision a FORMAT placeholder may ask for.
+const MAX_FORMAT_WIDTH: usize = 1000;
+
+/// Fills each placeholder in a FORMAT template with the next value. `{}`
+/// writes the value as PRINT would; `{:spec}` takes `[0][width][.precision]`.
+/// `{{` and `}}` are literal braces.
+pub(crate) fn format_template(template: &str, values: &[Value]) -> Result<String, String> {
+    let mut result = String::new();
+    let mut remaining = values.iter();
+    let mut chars = template.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' | '}' if chars.as_str().starts_with(ch) => {
+                chars.next();
+                result.push(ch);
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or("Unclosed { in FORMAT template; write {{ for a literal brace")?;
+                let value = remaining.next().ok_or_else(|| {
+                    format!(
+                        "FORMAT template has more placeholders than the {} values given",
+                        values.len()
+                    )
+                })?;
+                result.push_str(&format_placeholder(value, &rest[..end])?);
+                chars = rest[end + 1..].chars();
+            }
+            '}' => {
+                return Err("Unmatched } in FORMAT template; write }} for a literal brace".into());
+            }
+            _ => result.push(ch),
+        }
+    }
+    if remaining.next().is_some() {
+        return Err(format!(
+            "FORMAT was given {} values, more than its template has placeholders",
+            values.len()
+        ));
+    }
+    Ok(result)
+}
+
+/// Formats one value for a placeholder whose text between the braces is
+/// `spec`. Numbers are right-aligned and may be zero-padded after the sign;
+/// text is left-aligned, and a precision keeps only that many characters.
+fn format_placeholder(value: &Value, spec: &str) -> Result<String, String> {
+    let unsupported = || {
+        format!(
+            "Unsupported FORMAT placeholder {{{spec}}}; expected {{}} or {{:[0][width][.precision]}}"
+        )
+    };
+    let spec = if spec.is_empty() {
+        ""
+    } else {
+        spec.strip_prefix(':').ok_or_else(unsupported)?
+    };
+    let (zero_pad, sized) = match spec.strip_prefix('0') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, spec),
+    };
+    let (width, precision) = match sized.split_once('.') {
+        Some((width, precision)) => (width, Some(precision)),
+        None => (sized, None),
+    };
+    let parse = |digits: &str| {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(unsupported());
+        }
+        match digits.parse::<usize>() {
+            Ok(n) if n <= MAX_FORMAT_WIDTH => Ok(n),
+            _ => Err(format!(
+                "FORMAT width and precision may be at most {MAX_FORMAT_WIDTH}"
+            )),
+        }
+    };
+    let width = if width.is_empty() { 0 } else { parse(width)? };
+    let precision = precision.map(parse).transpose()?;
+
+    let (text, is_number) = match (value, precision) {
+        (Value::Number(n), Some(places)) => (format!("{:.places$}", n + 0.0), true),
+        (Value::Number(n), None) => (format_number(*n), true),
+        (Value::Text(s), Some(places)) => (s.chars().take(places).collect(), false),
+        (other, _) => (other.to_string(), false),
+    };
+    let padding = width.saturating_sub(text.chars().count());
+    Ok(match value {
+        Value::Number(n) if zero_pad && n.is_finite() => {
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", text.as_str()),
+            };
+            format!("{sign}{}{digits}", "0".repeat(padding))
+        }
+        _ if is_number => format!("{}{text}", " ".repeat(padding)),
+        _ => format!("{text}{}", " ".repeat(padding)),
+    })
+}
+
+/// Formats an array as `[1, 2, 3]`.
+pub(crate) fn format_array(values: &[f64]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format_number(*v)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Formats an array of strings as `["a", "b"]`.
+pub(crate) fn format_string_array(values: &[String]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format!("\"{v}\"")).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Formats a dictionary as `{a: 1, b: 2}`, sorted by key.
+pub(crate) fn format_dict(dict: &HashMap<String, f64>) -> String {
+    let mut keys: Vec<&String> = dict.keys().collect();
+    keys.sort();
+    let items: Vec<String> = keys
+        .iter()
+        .map(|k| format!("{k}: {}", format_number(dict[*k])))
+        .collect();
+    format!("{{{}}}", items.join(", "))
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{LazyLock, Mutex};
+
+/// Maps names to small integer ids and back. Each name is stored once, for
+/// the life of the program, so an id can hand out its name without copying.
+#[derive(Debug, Default)]
+pub struct StringInterner {
+    ids: HashMap<&'static str, u32>,
+    names: Vec<&'static str>,
+}
+
+impl StringInterner {
+    pub fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = u32::try_from(self.names.len()).expect("fewer than 2^32 distinct names");
+        let name: &'static str = Box::leak(name.into());
+        self.names.push(name);
+        self.ids.insert(name, id);
+        id
+    }
+
+    pub fn resolve(&self, id: u32) -> &'static str {
+        self.names[id as usize]
+    }
+}
+
+/// One interner for the whole program, so names parsed by IMPORT or USE, or
+/// made up while running, get the same ids as the main program's.
+static INTERNER: LazyLock<Mutex<StringInterner>> = LazyLock::new(Mutex::default);
+
+/// An interned identifier. The parser interns variable names, so looking a
+/// variable up hashes a number instead of its whole name.
+///
+/// ```
+/// use anubhav_lang::lang::Symbol;
+///
+/// let counter = Symbol::intern("counter");
+/// assert_eq!(counter, Symbol::intern("counter"));
+/// assert_ne!(counter, Symbol::intern("count"));
+/// assert_eq!(counter.as_str(), "counter");
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        Symbol(lock().intern(name))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        lock().resolve(self.0)
+    }
+}
+
+/// The interner stays usable even if a thread panicked while holding it, since
+/// every update leaves it consistent.
+fn lock() -> std::sync::MutexGuard<'static, StringInterner> {
+    INTERNER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl From<&Symbol> for Symbol {
+    fn from(name: &Symbol) -> Symbol {
+        *name
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::intern(&name)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Intent,
@@ -29,6 +31,9 @@ pub enum Token {
     Floor,
     Ceil,
     Round,
+    Abs,
+    Sign,
+    Sqrt,
     Random,
     Length,
     Substring,
@@ -117,9 +122,85 @@ pub enum Token {
     ModeOp,
     StdDev,
     Variance,
+    DictSize,
+    Record,
+    Field,
+    Scope,
+    Histogram,
+    PrintTable,
+    Into,
+    FnRef,
+    CallDynamic,
+    AssertNear,
+    Unless,
+    DumpState,
+    InputStr,
+    InputNum,
+    ReadLines,
+    WriteLines,
+    Extend,
+    ListDir,
+    FileSize,
+    DeleteFile,
+    RenameFile,
+    FormatThousands,
+    FormatSci,
+    IsInt,
+    IsEven,
+    IsOdd,
+    ArrayEq,
+    DictEq,
+    Cond,
+    When,
+    SetGrow,
+    ForRange,
+    Enumerate,
+    SliceStr,
+    MapDict,
+    FilterDict,
+    CountStr,
+    Capitalize,
+    TitleCase,
+    NormalizeWs,
+    Flush,
+    Trunc,
+    Fract,
+    ParseRadix,
+    ToRadix,
+    Gcd,
+    Lcm,
+    FormatDate,
+    DictGetOr,
+    Exit,
+    ZipWith,
+    RecallOr,
+    Collect,
+    From,
+    Where,
+    Select,
+    Use,
+    As,
+    Log,
+    SetLogLevel,
+    SleepSec,
+    ArrayCmp,
+    Let,
+    In,
+    Format,
+    ArrayToDict,
+    DictToArray,
+    RequireKeys,
+    ParseJson,
+    SerializeJson,
+    SumNumeric,
+    MinOfOr,
+    MaxOfOr,
+    Stats,
     Identifier(String),
+    Label(String), // `@name` after a loop's DO, or after BREAK/CONTINUE
     StringLiteral(String),
     Number(f64),
+    Invalid(String), // Text the lexer could not read, with the reason
     Plus,
     Minus,
     Star,
@@ -128,6 +209,11 @@ pub enum Token {
     Power,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot, // After a namespace, `geometry.AREA`, or before a chained method, `.reverse()`
+    Comma,
+    Terminator, // A newline or `;` ending a statement, when terminators are on
     Equal,
     NotEqual,
     Less,
@@ -140,45 +226,389 @@ pub enum Token {
     EOF,
 }
 
+/// Generates `keyword_for`, `all_keywords` and `keyword_text` from one table so the lexer
+/// and external tooling can never disagree about the keyword set.
+macro_rules! keywords {
+    ($($text:literal => $token:ident,)*) => {
+        /// Returns the token for a reserved word, or `None` for an identifier.
+        pub fn keyword_for(s: &str) -> Option<Token> {
+            match s {
+                $($text => Some(Token::$token),)*
+                _ => None,
+            }
+        }
+
+        /// Every reserved word, in lexer table order. Intended for editor
+        /// highlighting, completion and formatting.
+        pub fn all_keywords() -> &'static [&'static str] {
+            &[$($text,)*]
+        }
+
+        /// The source spelling of a keyword token.
+        pub(crate) fn keyword_text(token: &Token) -> Option<&'static str> {
+            match token {
+                $(Token::$token => Some($text),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+keywords! {
+    "INTENT" => Intent,
+    "MANIFEST" => Manifest,
+    "CALCULATE" => Calculate,
+    "WITH" => With,
+    "STORE" => Store,
+    "RECALL" => Recall,
+    "COMBINE" => Combine,
+    "REPEAT" => Repeat,
+    "TIMES" => Times,
+    "DO" => Do,
+    "END" => End,
+    "IF" => If,
+    "THEN" => Then,
+    "ELSE" => Else,
+    "AND" => And,
+    "OR" => Or,
+    "NOT" => Not,
+    "PRINT" => Print,
+    "WHILE" => While,
+    "INCREMENT" => Increment,
+    "DECREMENT" => Decrement,
+    "FOR" => For,
+    "TO" => To,
+    "STEP" => Step,
+    "MIN" => Min,
+    "MAX" => Max,
+    "ASSERT" => Assert,
+    "TRY" => Try,
+    "CATCH" => Catch,
+    "FLOOR" => Floor,
+    "CEIL" => Ceil,
+    "ROUND" => Round,
+    "ABS" => Abs,
+    "SIGN" => Sign,
+    "SQRT" => Sqrt,
+    "RANDOM" => Random,
+    "LENGTH" => Length,
+    "SUBSTRING" => Substring,
+    "UPPERCASE" => Uppercase,
+    "LOWERCASE" => Lowercase,
+    "CONTAINS" => Contains,
+    "SWITCH" => Switch,
+    "CASE" => Case,
+    "DEFAULT" => Default,
+    "ARRAY" => Array,
+    "PUSH" => Push,
+    "POP" => Pop,
+    "SIZE" => Size,
+    "GET" => Get,
+    "SET" => Set,
+    "IMPORT" => Import,
+    "EXPORT" => Export,
+    "BREAK" => Break,
+    "CONTINUE" => Continue,
+    "FUNCTION" => Function,
+    "CALL" => Call,
+    "RETURN" => Return,
+    "SORT" => Sort,
+    "FILTER" => Filter,
+    "REVERSE" => Reverse,
+    "MAP" => Map,
+    "REDUCE" => Reduce,
+    "SUM" => Sum,
+    "JOIN" => Join,
+    "DICT" => Dict,
+    "PUT" => Put,
+    "FETCH" => Fetch,
+    "KEYS" => Keys,
+    "VALUES" => Values,
+    "READ_FILE" => ReadFile,
+    "WRITE_FILE" => WriteFile,
+    "APPEND_FILE" => AppendFile,
+    "DELETE" => Delete,
+    "EXISTS" => Exists,
+    "SLEEP" => Sleep,
+    "INPUT" => Input,
+    "TYPE" => Type,
+    "PARSE" => Parse,
+    "TO_STRING" => ToString,
+    "LAMBDA" => Lambda,
+    "PIPE" => Pipe,
+    "RANGE" => RangeOp,
+    "FOLD" => Fold,
+    "FIND" => FindOp,
+    "ALL" => AllOp,
+    "ANY" => AnyOp,
+    "ZIP" => Zip,
+    "UNZIP" => Unzip,
+    "FLATTEN" => Flatten,
+    "UNIQUE" => Unique,
+    "COUNT" => CountOp,
+    "GROUP_BY" => GroupBy,
+    "PARTITION" => Partition,
+    "TAKE" => TakeOp,
+    "DROP" => DropOp,
+    "SLICE" => SliceOp,
+    "CONCAT" => Concat,
+    "SPLIT" => SplitOp,
+    "REPLACE" => ReplaceOp,
+    "TRIM" => Trim,
+    "STARTS_WITH" => StartsWith,
+    "ENDS_WITH" => EndsWith,
+    "INCLUDES" => Includes,
+    "INDEX_OF" => IndexOfOp,
+    "PAD" => Pad,
+    "EVAL" => Eval,
+    "TYPE_OF" => TypeOfOp,
+    "CLONE" => CloneOp,
+    "MERGE" => Merge,
+    "DIFF" => Diff,
+    "INTERSECTION" => Intersection,
+    "UNION" => UnionOp,
+    "CLEAR" => ClearOp,
+    "SWAP" => SwapOp,
+    "SHUFFLE" => Shuffle,
+    "SAMPLE" => Sample,
+    "MIN_OF" => MinOfOp,
+    "MAX_OF" => MaxOfOp,
+    "AVERAGE" => AverageOp,
+    "MEDIAN" => Median,
+    "MODE" => ModeOp,
+    "STDDEV" => StdDev,
+    "VARIANCE" => Variance,
+    "DICT_SIZE" => DictSize,
+    "RECORD" => Record,
+    "FIELD" => Field,
+    "SCOPE" => Scope,
+    "HISTOGRAM" => Histogram,
+    "PRINT_TABLE" => PrintTable,
+    "INTO" => Into,
+    "FN_REF" => FnRef,
+    "CALL_DYNAMIC" => CallDynamic,
+    "ASSERT_NEAR" => AssertNear,
+    "UNLESS" => Unless,
+    "DUMP_STATE" => DumpState,
+    "INPUT_STR" => InputStr,
+    "INPUT_NUM" => InputNum,
+    "READ_LINES" => ReadLines,
+    "WRITE_LINES" => WriteLines,
+    "EXTEND" => Extend,
+    "LIST_DIR" => ListDir,
+    "FILE_SIZE" => FileSize,
+    "DELETE_FILE" => DeleteFile,
+    "RENAME_FILE" => RenameFile,
+    "FORMAT_THOUSANDS" => FormatThousands,
+    "FORMAT_SCI" => FormatSci,
+    "IS_INT" => IsInt,
+    "IS_EVEN" => IsEven,
+    "IS_ODD" => IsOdd,
+    "ARRAY_EQ" => ArrayEq,
+    "DICT_EQ" => DictEq,
+    "COND" => Cond,
+    "WHEN" => When,
+    "SET_GROW" => SetGrow,
+    "FOR_RANGE" => ForRange,
+    "ENUMERATE" => Enumerate,
+    "SLICE_STR" => SliceStr,
+    "MAP_DICT" => MapDict,
+    "FILTER_DICT" => FilterDict,
+    "COUNT_STR" => CountStr,
+    "CAPITALIZE" => Capitalize,
+    "TITLE_CASE" => TitleCase,
+    "NORMALIZE_WS" => NormalizeWs,
+    "FLUSH" => Flush,
+    "TRUNC" => Trunc,
+    "FRACT" => Fract,
+    "PARSE_RADIX" => ParseRadix,
+    "TO_RADIX" => ToRadix,
+    "GCD" => Gcd,
+    "LCM" => Lcm,
+    "FORMAT_DATE" => FormatDate,
+    "DICT_GET_OR" => DictGetOr,
+    "EXIT" => Exit,
+    "ZIP_WITH" => ZipWith,
+    "RECALL_OR" => RecallOr,
+    "COLLECT" => Collect,
+    "FROM" => From,
+    "WHERE" => Where,
+    "SELECT" => Select,
+    "USE" => Use,
+    "AS" => As,
+    "LOG" => Log,
+    "SET_LOG_LEVEL" => SetLogLevel,
+    "SLEEP_SEC" => SleepSec,
+    "ARRAY_CMP" => ArrayCmp,
+    "LET" => Let,
+    "IN" => In,
+    "FORMAT" => Format,
+    "ARRAY_TO_DICT" => ArrayToDict,
+    "DICT_TO_ARRAY" => DictToArray,
+    "REQUIRE_KEYS" => RequireKeys,
+    "PARSE_JSON" => ParseJson,
+    "SERIALIZE_JSON" => SerializeJson,
+    "SUM_NUMERIC" => SumNumeric,
+    "MIN_OF_OR" => MinOfOr,
+    "MAX_OF_OR" => MaxOfOr,
+    "STATS" => Stats,
+}
+
+/// Renders a token as it would appear in source, for error messages.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(text) = keyword_text(self) {
+            return write!(f, "{text}");
+        }
+        match self {
+            Token::Identifier(name) => write!(f, "{name}"),
+            Token::Label(name) => write!(f, "@{name}"),
+            Token::StringLiteral(s) => write!(f, "\"{s}\""),
+            Token::Invalid(reason) => write!(f, "{reason}"),
+            Token::Number(n) => write!(f, "{n}"),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Power => write!(f, "**"),
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::LeftBracket => write!(f, "["),
+            Token::RightBracket => write!(f, "]"),
+            Token::Dot => write!(f, "."),
+            Token::Comma => write!(f, ","),
+            Token::Terminator => write!(f, "end of statement"),
+            Token::Equal => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::Less => write!(f, "<"),
+            Token::Greater => write!(f, ">"),
+            Token::LessEqual => write!(f, "<="),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::EOF => write!(f, "end of file"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Lexer {
     input: String,
     position: usize,
     current_char: Option<char>,
+    line: usize,
+    token_line: usize, // Line the most recently returned token started on
+    terminators: bool, // Whether newlines and `;` end statements
+    nesting: usize,    // Open ( and [, inside which newlines are whitespace
 }
 
 impl Lexer {
     pub fn new(input: String) -> Self {
+        // A UTF-8 byte order mark is not part of the program
+        let input = match input.strip_prefix('\u{feff}') {
+            Some(rest) => rest.to_string(),
+            None => input,
+        };
         let mut lexer = Lexer {
             input,
             position: 0,
             current_char: None,
+            line: 1,
+            token_line: 1,
+            terminators: false,
+            nesting: 0,
         };
         lexer.current_char = lexer.input.chars().nth(0);
         lexer
     }
 
+    /// The 1-based line of the token last returned by `next_token`.
+    pub fn token_line(&self) -> usize {
+        self.token_line
+    }
+
+    /// Makes a newline or `;` end a statement, returned as
+    /// `Token::Terminator`. Newlines inside parentheses or brackets still
+    /// count as whitespace, so a long expression can span lines. Off by
+    /// default, where statements are told apart by their keywords alone.
+    ///
+    /// ```
+    /// use anubhav_lang::lang::{Lexer, Parser};
+    ///
+    /// let source = "ARRAY nums; PUSH nums 2\nPRINT \"sorting\"\nnums.sort() INTO s";
+    /// let mut lexer = Lexer::new(source.to_string());
+    /// lexer.set_terminators(true);
+    /// assert_eq!(Parser::new(lexer).parse().unwrap().len(), 4);
+    ///
+    /// let mut lexer = Lexer::new("STORE x 1 STORE y 2".to_string());
+    /// lexer.set_terminators(true);
+    /// assert!(Parser::new(lexer).parse().is_err());
+    /// ```
+    pub fn set_terminators(&mut self, terminators: bool) {
+        self.terminators = terminators;
+    }
+
+    /// Whether newlines and `;` end statements.
+    pub fn terminators(&self) -> bool {
+        self.terminators
+    }
+
     fn advance(&mut self) {
+        if self.current_char == Some('\n') {
+            self.line += 1;
+        }
         self.position += 1;
         self.current_char = self.input.chars().nth(self.position);
     }
 
-    fn skip_whitespace(&mut self) {
+    fn peek(&self) -> Option<char> {
+        self.input.chars().nth(self.position + 1)
+    }
+
+    /// Whether `\"\"\"` starts at the current character.
+    fn at_triple_quote(&self) -> bool {
+        self.input
+            .chars()
+            .skip(self.position)
+            .take(3)
+            .eq("\"\"\"".chars())
+    }
+
+    /// Skips whitespace and comments. An unterminated block comment is
+    /// returned as a `Token::Invalid` naming the line it starts on.
+    fn skip_whitespace(&mut self) -> Result<(), Token> {
         while let Some(ch) = self.current_char {
-            if ch.is_whitespace() {
+            if ch == '\n' && self.terminators && self.nesting == 0 {
+                break;
+            } else if ch.is_whitespace() {
                 self.advance();
-            } else if ch == '#' {
-                // Skip comment line
-                while let Some(c) = self.current_char {
-                    if c == '\n' {
+            } else if ch == '/' && self.peek() == Some('*') {
+                let start_line = self.line;
+                self.advance();
+                self.advance();
+                loop {
+                    let Some(c) = self.current_char else {
+                        self.token_line = start_line;
+                        return Err(Token::Invalid(format!(
+                            "Unterminated block comment starting at line {start_line}"
+                        )));
+                    };
+                    self.advance();
+                    if c == '*' && self.current_char == Some('/') {
                         self.advance();
                         break;
                     }
+                }
+            } else if ch == '#' {
+                // Skip comment line, leaving its newline
+                while self.current_char.is_some_and(|c| c != '\n') {
                     self.advance();
                 }
             } else {
                 break;
             }
         }
+        Ok(())
     }
 
     fn read_string(&mut self) -> String {
@@ -196,6 +626,30 @@ impl Lexer {
         result
     }
 
+    /// Reads a `\"\"\"` string, which runs verbatim across lines up to the
+    /// next `\"\"\"`.
+    fn read_heredoc(&mut self) -> Token {
+        let start_line = self.line;
+        for _ in 0..3 {
+            self.advance(); // Skip opening quotes
+        }
+
+        let mut result = String::new();
+        while let Some(ch) = self.current_char {
+            if self.at_triple_quote() {
+                for _ in 0..3 {
+                    self.advance(); // Skip closing quotes
+                }
+                return Token::StringLiteral(result);
+            }
+            result.push(ch);
+            self.advance();
+        }
+        Token::Invalid(format!(
+            "Unterminated \"\"\" string starting at line {start_line}"
+        ))
+    }
+
     fn read_identifier(&mut self) -> String {
         let mut result = String::new();
 
@@ -214,7 +668,7 @@ impl Lexer {
         let mut result = String::new();
 
         while let Some(ch) = self.current_char {
-            if ch.is_numeric() || ch == '.' {
+            if ch.is_ascii_digit() || ch == '.' {
                 result.push(ch);
                 self.advance();
             } else {
@@ -225,11 +679,38 @@ impl Lexer {
         result.parse().unwrap_or(0.0)
     }
 
+    /// Reads the next token. A `.` before a name is always a token of its
+    /// own; the parser decides what it joins.
+    ///
+    /// ```
+    /// use anubhav_lang::lang::{Lexer, Parser, Token};
+    ///
+    /// let mut lexer = Lexer::new("geometry.AREA".to_string());
+    /// assert_eq!(lexer.next_token(), Token::Identifier("geometry".to_string()));
+    /// assert_eq!(lexer.next_token(), Token::Dot);
+    /// assert_eq!(lexer.next_token(), Token::Identifier("AREA".to_string()));
+    ///
+    /// // So a variable name cannot contain one
+    /// assert!(Parser::new(Lexer::new("STORE a.b 5".to_string())).parse().is_err());
+    /// ```
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+        if let Err(invalid) = self.skip_whitespace() {
+            return invalid;
+        }
+        self.token_line = self.line;
 
         match self.current_char {
             None => Token::EOF,
+            // Only reached with terminators on; otherwise skipped as whitespace
+            Some('\n') => {
+                self.advance();
+                Token::Terminator
+            }
+            Some(';') if self.terminators => {
+                self.advance();
+                Token::Terminator
+            }
+            Some('"') if self.at_triple_quote() => self.read_heredoc(),
             Some('"') => {
                 let string_val = self.read_string();
                 Token::StringLiteral(string_val)
@@ -297,141 +778,43 @@ impl Lexer {
             }
             Some('(') => {
                 self.advance();
+                self.nesting += 1;
                 Token::LeftParen
             }
             Some(')') => {
                 self.advance();
+                self.nesting = self.nesting.saturating_sub(1);
                 Token::RightParen
             }
-            Some(ch) if ch.is_numeric() => {
+            Some('[') => {
+                self.advance();
+                self.nesting += 1;
+                Token::LeftBracket
+            }
+            Some(']') => {
+                self.advance();
+                self.nesting = self.nesting.saturating_sub(1);
+                Token::RightBracket
+            }
+            Some(',') => {
+                self.advance();
+                Token::Comma
+            }
+            Some('.') if self.peek().is_some_and(|c| c.is_alphabetic() || c == '_') => {
+                self.advance();
+                Token::Dot
+            }
+            Some('@') => {
+                self.advance();
+                Token::Label(self.read_identifier())
+            }
+            Some(ch) if ch.is_ascii_digit() => {
                 let num = self.read_number();
                 Token::Number(num)
             }
             Some(ch) if ch.is_alphabetic() => {
                 let identifier = self.read_identifier();
-                match identifier.as_str() {
-                    "INTENT" => Token::Intent,
-                    "MANIFEST" => Token::Manifest,
-                    "CALCULATE" => Token::Calculate,
-                    "WITH" => Token::With,
-                    "STORE" => Token::Store,
-                    "RECALL" => Token::Recall,
-                    "COMBINE" => Token::Combine,
-                    "REPEAT" => Token::Repeat,
-                    "TIMES" => Token::Times,
-                    "DO" => Token::Do,
-                    "END" => Token::End,
-                    "IF" => Token::If,
-                    "THEN" => Token::Then,
-                    "ELSE" => Token::Else,
-                    "AND" => Token::And,
-                    "OR" => Token::Or,
-                    "NOT" => Token::Not,
-                    "PRINT" => Token::Print,
-                    "WHILE" => Token::While,
-                    "INCREMENT" => Token::Increment,
-                    "DECREMENT" => Token::Decrement,
-                    "FOR" => Token::For,
-                    "TO" => Token::To,
-                    "STEP" => Token::Step,
-                    "MIN" => Token::Min,
-                    "MAX" => Token::Max,
-                    "ASSERT" => Token::Assert,
-                    "TRY" => Token::Try,
-                    "CATCH" => Token::Catch,
-                    "FLOOR" => Token::Floor,
-                    "CEIL" => Token::Ceil,
-                    "ROUND" => Token::Round,
-                    "RANDOM" => Token::Random,
-                    "LENGTH" => Token::Length,
-                    "SUBSTRING" => Token::Substring,
-                    "UPPERCASE" => Token::Uppercase,
-                    "LOWERCASE" => Token::Lowercase,
-                    "CONTAINS" => Token::Contains,
-                    "SWITCH" => Token::Switch,
-                    "CASE" => Token::Case,
-                    "DEFAULT" => Token::Default,
-                    "ARRAY" => Token::Array,
-                    "PUSH" => Token::Push,
-                    "POP" => Token::Pop,
-                    "SIZE" => Token::Size,
-                    "GET" => Token::Get,
-                    "SET" => Token::Set,
-                    "IMPORT" => Token::Import,
-                    "EXPORT" => Token::Export,
-                    "BREAK" => Token::Break,
-                    "CONTINUE" => Token::Continue,
-                    "FUNCTION" => Token::Function,
-                    "CALL" => Token::Call,
-                    "RETURN" => Token::Return,
-                    "SORT" => Token::Sort,
-                    "FILTER" => Token::Filter,
-                    "REVERSE" => Token::Reverse,
-                    "MAP" => Token::Map,
-                    "REDUCE" => Token::Reduce,
-                    "SUM" => Token::Sum,
-                    "JOIN" => Token::Join,
-                    "DICT" => Token::Dict,
-                    "PUT" => Token::Put,
-                    "FETCH" => Token::Fetch,
-                    "KEYS" => Token::Keys,
-                    "VALUES" => Token::Values,
-                    "READ_FILE" => Token::ReadFile,
-                    "WRITE_FILE" => Token::WriteFile,
-                    "APPEND_FILE" => Token::AppendFile,
-                    "DELETE" => Token::Delete,
-                    "EXISTS" => Token::Exists,
-                    "SLEEP" => Token::Sleep,
-                    "INPUT" => Token::Input,
-                    "TYPE" => Token::Type,
-                    "PARSE" => Token::Parse,
-                    "TO_STRING" => Token::ToString,
-                    "LAMBDA" => Token::Lambda,
-                    "PIPE" => Token::Pipe,
-                    "RANGE" => Token::RangeOp,
-                    "FOLD" => Token::Fold,
-                    "FIND" => Token::FindOp,
-                    "ALL" => Token::AllOp,
-                    "ANY" => Token::AnyOp,
-                    "ZIP" => Token::Zip,
-                    "UNZIP" => Token::Unzip,
-                    "FLATTEN" => Token::Flatten,
-                    "UNIQUE" => Token::Unique,
-                    "COUNT" => Token::CountOp,
-                    "GROUP_BY" => Token::GroupBy,
-                    "PARTITION" => Token::Partition,
-                    "TAKE" => Token::TakeOp,
-                    "DROP" => Token::DropOp,
-                    "SLICE" => Token::SliceOp,
-                    "CONCAT" => Token::Concat,
-                    "SPLIT" => Token::SplitOp,
-                    "REPLACE" => Token::ReplaceOp,
-                    "TRIM" => Token::Trim,
-                    "STARTS_WITH" => Token::StartsWith,
-                    "ENDS_WITH" => Token::EndsWith,
-                    "INCLUDES" => Token::Includes,
-                    "INDEX_OF" => Token::IndexOfOp,
-                    "PAD" => Token::Pad,
-                    "EVAL" => Token::Eval,
-                    "TYPE_OF" => Token::TypeOfOp,
-                    "CLONE" => Token::CloneOp,
-                    "MERGE" => Token::Merge,
-                    "DIFF" => Token::Diff,
-                    "INTERSECTION" => Token::Intersection,
-                    "UNION" => Token::UnionOp,
-                    "CLEAR" => Token::ClearOp,
-                    "SWAP" => Token::SwapOp,
-                    "SHUFFLE" => Token::Shuffle,
-                    "SAMPLE" => Token::Sample,
-                    "MIN_OF" => Token::MinOfOp,
-                    "MAX_OF" => Token::MaxOfOp,
-                    "AVERAGE" => Token::AverageOp,
-                    "MEDIAN" => Token::Median,
-                    "MODE" => Token::ModeOp,
-                    "STDDEV" => Token::StdDev,
-                    "VARIANCE" => Token::Variance,
-                    _ => Token::Identifier(identifier),
-                }
+                keyword_for(&identifier).unwrap_or(Token::Identifier(identifier))
             }
             _ => {
                 self.advance();
@@ -1,5 +1,10 @@
+pub mod interner;
 pub mod lexer;
 pub mod parser;
 
-pub use lexer::{Lexer, Token};
-pub use parser::{Expression, Parser, Statement};
+pub use interner::Symbol;
+pub use lexer::{Lexer, Token, all_keywords, keyword_for};
+pub use parser::{
+    CombinePart, Expression, InputMode, LogLevel, Operand, Parser, RecordField, Statement,
+    StatementLines,
+};
@@ -1,14 +1,246 @@
-use crate::lexer::{Lexer, Token};
+use crate::lang::interner::Symbol;
+use crate::lexer::{Lexer, Token, keyword_text};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum Expression {
     Number(f64),
-    Recall(String),
+    /// A string literal. Only `* count`, which repeats it, applies to text
+    Text(String),
+    Recall(Symbol),
     BinaryOp {
         left: Box<Expression>,
         operator: Token,
         right: Box<Expression>,
     },
+    /// An element of a numeric array: `arr[RECALL i]`
+    Index {
+        array: String,
+        index: Box<Expression>,
+    },
+    /// `RECALL_OR name default`: the variable, or `default` when it is undefined
+    RecallOr {
+        name: Symbol,
+        default: Box<Expression>,
+    },
+    /// `LET name = value IN body`: `body` with `name` bound to `value`
+    Let {
+        name: Symbol,
+        value: Box<Expression>,
+        body: Box<Expression>,
+    },
+    /// `CALL name(args)`: the single value the function returns
+    Call {
+        function_name: String,
+        arguments: Vec<Expression>,
+    },
+}
+
+/// Binding strength of a rendered expression, matching the parser: LET (whose
+/// body extends as far as it can), OR, AND, `+ -`, comparisons, `* / %`, `**`,
+/// then primaries (numbers, RECALL, unary minus, NOT and function calls).
+const PRIMARY: u8 = 7;
+
+impl Expression {
+    fn precedence(&self) -> u8 {
+        let (left, operator) = match self {
+            Expression::BinaryOp { left, operator, .. } => (left, operator),
+            Expression::Let { .. } => return 0,
+            _ => return PRIMARY,
+        };
+        match operator {
+            Token::Or => 1,
+            Token::And => 2,
+            Token::Minus if is_dummy(left) => PRIMARY,
+            Token::Plus | Token::Minus => 3,
+            Token::Equal
+            | Token::NotEqual
+            | Token::Less
+            | Token::Greater
+            | Token::LessEqual
+            | Token::GreaterEqual => 4,
+            Token::Star | Token::Slash | Token::Percent => 5,
+            Token::Power => 6,
+            _ => PRIMARY,
+        }
+    }
+}
+
+/// The placeholder left operand of unary minus, NOT and one-argument functions.
+fn is_dummy(expr: &Expression) -> bool {
+    matches!(expr, Expression::Number(n) if *n == 0.0)
+}
+
+/// Writes `expr`, in parentheses when it binds less tightly than `min`.
+fn write_operand(f: &mut fmt::Formatter, expr: &Expression, min: u8) -> fmt::Result {
+    if expr.precedence() < min {
+        write!(f, "({expr})")
+    } else {
+        write!(f, "{expr}")
+    }
+}
+
+/// Renders source text that parses back to the same tree, with parentheses
+/// only where the parser's precedence needs them: `RECALL a * (RECALL b + 1)`.
+///
+/// ```
+/// use anubhav_lang::lang::{Expression, Lexer, Parser, Statement};
+///
+/// fn expression(source: &str) -> Expression {
+///     let source = format!("CALCULATE x {source}");
+///     let mut statements = Parser::new(Lexer::new(source)).parse().unwrap();
+///     match statements.remove(0) {
+///         Statement::Calculate { expression, .. } => expression,
+///         other => panic!("not a CALCULATE: {other:?}"),
+///     }
+/// }
+///
+/// for (source, shown) in [
+///     ("(1 + 2) * 3", "(1 + 2) * 3"),
+///     ("1 + (2 * 3)", "1 + 2 * 3"),
+///     ("10 - (4 - 3)", "10 - (4 - 3)"),
+///     ("(10 - 4) - 3", "10 - 4 - 3"),
+///     ("(-RECALL a) * 2", "-RECALL a * 2"),
+///     ("-(RECALL a + 1)", "-(RECALL a + 1)"),
+///     ("(-5) + 1", "-5 + 1"),
+///     ("\"ab\" * 3", "\"ab\" * 3"),
+///     ("CALL f(1 + 2, RECALL a) * 2", "CALL f(1 + 2, RECALL a) * 2"),
+/// ] {
+///     let parsed = expression(source);
+///     assert_eq!(parsed.to_string(), shown);
+///     // Reading the text back gives the same tree
+///     let reparsed = expression(&parsed.to_string());
+///     assert_eq!(format!("{reparsed:?}"), format!("{parsed:?}"));
+/// }
+/// ```
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (left, operator, right) = match self {
+            Expression::Number(n) if *n < 0.0 => return write!(f, "(-{})", -n),
+            Expression::Number(n) => return write!(f, "{n}"),
+            Expression::Text(text) => return write!(f, "\"{text}\""),
+            Expression::Recall(name) => return write!(f, "RECALL {name}"),
+            Expression::Index { array, index } => return write!(f, "{array}[{index}]"),
+            Expression::RecallOr { name, default } => {
+                write!(f, "RECALL_OR {name} ")?;
+                return write_operand(f, default, PRIMARY);
+            }
+            Expression::Let { name, value, body } => {
+                return write!(f, "LET {name} = {value} IN {body}");
+            }
+            Expression::Call {
+                function_name,
+                arguments,
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                return write!(f, "CALL {function_name}({})", arguments.join(", "));
+            }
+            Expression::BinaryOp {
+                left,
+                operator,
+                right,
+            } => (left, operator, right),
+        };
+
+        match operator {
+            Token::Minus | Token::Not if is_dummy(left) => {
+                let prefix = if *operator == Token::Not { "NOT " } else { "-" };
+                write!(f, "{prefix}")?;
+                write_operand(f, right, PRIMARY)
+            }
+            Token::Min | Token::Max | Token::Gcd | Token::Lcm => {
+                write!(f, "{operator}(")?;
+                write_operand(f, left, PRIMARY)?;
+                write!(f, ", ")?;
+                write_operand(f, right, PRIMARY)?;
+                write!(f, ")")
+            }
+            Token::Random => write!(f, "RANDOM()"),
+            Token::Size | Token::Length | Token::DictSize => match &**left {
+                Expression::Recall(name) => write!(f, "{operator}({name})"),
+                other => write!(f, "{operator}({other})"),
+            },
+            Token::Power => {
+                // Right-associative: only the left side needs parentheses for another **
+                write_operand(f, left, PRIMARY)?;
+                write!(f, " ** ")?;
+                write_operand(f, right, 6)
+            }
+            _ if self.precedence() < PRIMARY => {
+                let level = self.precedence();
+                write_operand(f, left, level)?;
+                write!(f, " {operator} ")?;
+                write_operand(f, right, level + 1)
+            }
+            // One-argument functions such as FLOOR and SQRT
+            _ => {
+                write!(f, "{operator}(")?;
+                write_operand(f, right, PRIMARY)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// The value given for a field in a `RECORD` declaration.
+#[derive(Debug, Clone)]
+pub enum RecordField {
+    Expression(Expression),
+    Text(String),
+    /// Name of an existing record, copied in as a nested record
+    Record(String),
+}
+
+/// One piece of a `COMBINE` statement.
+#[derive(Debug, Clone)]
+pub enum CombinePart {
+    Literal(String),
+    /// A bare identifier (or a `"${name}"` literal), looked up when the statement runs
+    Variable(String),
+    /// A parenthesized expression, `RECALL` or number, formatted like PRINT formats numbers;
+    /// or a literal or name followed by `*`, which repeats text and multiplies numbers
+    Expression(Expression),
+}
+
+/// A value compared against array elements: a number or a string literal.
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Expression(Expression),
+    Text(String),
+}
+
+/// How an `INPUT` line is stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputMode {
+    /// `INPUT`: a number if the text parses as one, otherwise a string
+    Auto,
+    /// `INPUT_STR`: always a string
+    Text,
+    /// `INPUT_NUM`: always a number, asking again until one is entered
+    Number,
+}
+
+/// Severity of a `LOG` message, lowest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        };
+        write!(f, "{name}")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,16 +258,17 @@ pub enum Statement {
         expression: Expression,
     },
     Store {
-        name: String,
+        name: Symbol,
         value: Expression,
     },
     Combine {
         name: String,
-        parts: Vec<String>,
+        parts: Vec<CombinePart>,
     },
     Repeat {
         count: Expression,
         body: Vec<Statement>,
+        label: Option<String>,
     },
     If {
         condition: Expression,
@@ -48,19 +281,21 @@ pub enum Statement {
     While {
         condition: Expression,
         body: Vec<Statement>,
+        label: Option<String>,
     },
     Increment {
-        variable: String,
+        variable: Symbol,
     },
     Decrement {
-        variable: String,
+        variable: Symbol,
     },
     For {
-        variable: String,
+        variable: Symbol,
         start: Expression,
         end: Expression,
         step: Option<Expression>,
         body: Vec<Statement>,
+        label: Option<String>,
     },
     Assert {
         condition: Expression,
@@ -107,25 +342,30 @@ pub enum Statement {
     },
     Import {
         filename: String,
+        namespace: Option<String>, // `AS name`: functions are called as `name.FUNC`
     },
     Export {
         items: Vec<String>,
         filename: String,
     },
-    Break,
-    Continue,
+    Break {
+        label: Option<String>, // Innermost loop when None
+    },
+    Continue {
+        label: Option<String>,
+    },
     FunctionDefinition {
         name: String,
         parameters: Vec<String>,
-        body: Vec<Statement>,
+        body: Rc<Vec<Statement>>,
     },
     FunctionCall {
         function_name: String,
         arguments: Vec<Expression>,
-        result_name: Option<String>,
+        result_names: Vec<String>,
     },
     Return {
-        value: Option<Expression>,
+        values: Vec<Expression>,
     },
     ArraySort {
         array_name: String,
@@ -200,6 +440,7 @@ pub enum Statement {
     Input {
         prompt: String,
         result_name: String,
+        mode: InputMode,
     },
     GetType {
         variable: String,
@@ -208,6 +449,7 @@ pub enum Statement {
     ParseNumber {
         source: String,
         result_name: String,
+        status_name: Option<String>, // Set to 1 on success, 0 on failure
     },
     Range {
         start: Expression,
@@ -284,235 +526,716 @@ pub enum Statement {
         source: String,
         destination: String,
     },
+    DictSize {
+        result_name: String,
+        dict_name: String,
+    },
+    RecordCreate {
+        name: String,
+        fields: Vec<(String, RecordField)>,
+    },
+    RecordField {
+        result_name: String,
+        record_name: String,
+        field: String,
+    },
+    Scope {
+        body: Vec<Statement>,
+    },
+    Histogram {
+        array_name: String,
+        width: Option<Expression>,
+    },
+    PrintTable {
+        dict_name: String,
+    },
+    FunctionRef {
+        name: String,
+        function_name: String,
+    },
+    DynamicCall {
+        ref_name: String,
+        arguments: Vec<Expression>,
+        result_names: Vec<String>,
+    },
+    AssertNear {
+        left: Expression,
+        right: Expression,
+        tolerance: Expression,
+        message: Option<String>,
+    },
+    Unless {
+        condition: Expression,
+        body: Vec<Statement>,
+    },
+    DumpState,
+    ReadLines {
+        filename: String,
+        result_array: String,
+    },
+    WriteLines {
+        filename: String,
+        array_name: String,
+    },
+    Extend {
+        dest: String,
+        src: String,
+    },
+    ListDir {
+        path: String,
+        result_array: String,
+    },
+    FileSize {
+        result_name: String,
+        filename: String,
+    },
+    DeleteFile {
+        filename: String,
+    },
+    RenameFile {
+        from: String,
+        to: String,
+    },
+    FormatThousands {
+        result_name: String,
+        value: Expression,
+    },
+    FormatSci {
+        result_name: String,
+        value: Expression,
+        places: Expression,
+    },
+    ArrayEq {
+        result_name: String,
+        first: String,
+        second: String,
+    },
+    DictEq {
+        result_name: String,
+        first: String,
+        second: String,
+    },
+    Cond {
+        branches: Vec<(Expression, Vec<Statement>)>,
+        else_body: Option<Vec<Statement>>,
+    },
+    ArraySetGrow {
+        array_name: String,
+        index: Expression,
+        value: Expression,
+    },
+    ForRange {
+        variable: String,
+        start: Expression,
+        end: Expression,
+        step: Option<Expression>,
+        body: Vec<Statement>,
+        label: Option<String>,
+    },
+    Enumerate {
+        array_name: String,
+        index_var: String,
+        value_var: String,
+        body: Vec<Statement>,
+        label: Option<String>,
+    },
+    SliceStr {
+        result_name: String,
+        source: String,
+        start: Expression,
+        end: Expression,
+    },
+    Includes {
+        result_name: String,
+        array_name: String,
+        value: Operand,
+    },
+    MapDict {
+        dict_name: String,
+        expression: Expression,
+        result_dict: String,
+    },
+    FilterDict {
+        dict_name: String,
+        condition: Expression,
+        result_dict: String,
+    },
+    CountStr {
+        result_name: String,
+        source: String,
+        pattern: String,
+    },
+    Flush,
+    ParseRadix {
+        source: String, // A string variable as `${name}`, otherwise literal text
+        radix: Expression,
+        result_name: String,
+        status_name: Option<String>, // Set to 1 on success, 0 on failure
+    },
+    ToRadix {
+        result_name: String,
+        value: Expression,
+        radix: Expression,
+    },
+    FormatDate {
+        result_name: String,
+        epoch: Expression, // Milliseconds since 1970-01-01T00:00:00 UTC
+        pattern: String,
+    },
+    DictGetOr {
+        dict_name: String,
+        key: String,
+        default: Expression, // Stored under `key` when it is missing
+        result_name: String,
+    },
+    Exit {
+        code: Option<Expression>, // Process exit code; 0 when omitted
+    },
+    ZipWith {
+        a: String,
+        b: String,
+        expression: Expression, // Evaluated with `left` and `right` bound to a pair
+        result_array: String,
+    },
+    Collect {
+        result_array: String,
+        start: Expression,
+        end: Expression,
+        step: Option<Expression>,
+        condition: Option<Expression>, // WHERE; every value is kept when omitted
+        expression: Expression,        // SELECT
+    },
+    Use {
+        filename: String,
+    },
+    Log {
+        level: LogLevel,
+        items: Vec<String>, // As for PRINT
+    },
+    SetLogLevel {
+        level: LogLevel,
+    },
+    SleepSec {
+        seconds: Expression,
+    },
+    ArrayCmp {
+        result_name: String,
+        first: String,
+        second: String,
+    },
+    Format {
+        result_name: String,
+        template: String,
+        arguments: Vec<CombinePart>, // One per placeholder; never Repeat
+    },
+    ArrayToDict {
+        array_name: String,
+        dict_name: String,
+    },
+    DictToArray {
+        dict_name: String,
+        array_name: String,
+    },
+    RequireKeys {
+        dict_name: String,
+        keys: Vec<String>, // As for PUT: literal keys or `${name}`
+    },
+    /// `source.sort().take(3) INTO result`: copies `source` to `result`, then
+    /// runs `steps`, each an array statement working on `result` in place
+    ArrayChain {
+        source: String,
+        result_array: String,
+        steps: Vec<Statement>,
+    },
+    ParseJson {
+        result_name: String,
+        source: String, // JSON text, or `${name}` for an intent holding it
+    },
+    SerializeJson {
+        result_name: String,
+        source: String, // A record, array, dictionary, string or number
+    },
+    SumNumeric {
+        array_name: String,
+        result_name: String,
+        lenient: bool, // Skip elements that are not numbers instead of failing
+    },
+    /// MIN_OF and MAX_OF, or with a default MIN_OF_OR and MAX_OF_OR
+    ArrayExtreme {
+        array_name: String,
+        result_name: String,
+        max: bool,
+        default: Option<Expression>, // Stored for an empty array, which is otherwise an error
+    },
+    Stats {
+        array_name: String,
+        prefix: String, // Results go in prefix_min, prefix_max, prefix_sum, ...
+    },
+}
+
+/// The source line each parsed statement starts on. Statements are looked up
+/// by address, so this covers the tree `parse` returned rather than copies of
+/// it; a function body keeps its statements in place, being shared by `Rc`.
+#[derive(Debug, Default)]
+pub struct StatementLines(HashMap<*const Statement, usize>);
+
+impl StatementLines {
+    pub fn line_of(&self, statement: &Statement) -> Option<usize> {
+        self.0.get(&(statement as *const Statement)).copied()
+    }
 }
 
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
+    current_line: usize,            // Line `current_token` starts on
+    previous_line: usize,           // Line the token before `current_token` starts on
+    peeked: Option<(Token, usize)>, // The token after `current_token` and its line, once peeked
+    warnings: Vec<String>,
+    loops: Vec<Option<String>>, // Labels of enclosing REPEAT/WHILE/FOR bodies in the current function
+    in_function: bool,          // Whether a FUNCTION body is being parsed
+    recovering: bool,           // Whether statement errors are collected instead of returned
+    errors: Vec<String>,        // Errors collected by parse_all_errors
+    analysis: Vec<String>,      // Likely runtime errors, reported as warnings by parse_all_errors
+    lines: StatementLines,
+    bare_names: bool, // Whether a bare name in an expression reads as RECALL name, as in COMBINE
 }
 
 impl Parser {
     pub fn new(mut lexer: Lexer) -> Self {
         let current_token = lexer.next_token();
         Parser {
+            current_line: lexer.token_line(),
+            previous_line: lexer.token_line(),
             lexer,
             current_token,
+            peeked: None,
+            warnings: Vec::new(),
+            loops: Vec::new(),
+            in_function: false,
+            recovering: false,
+            errors: Vec::new(),
+            analysis: Vec::new(),
+            lines: StatementLines::default(),
+            bare_names: false,
         }
     }
 
+    /// Non-fatal problems found while parsing, such as unreachable code.
+    ///
+    /// ```
+    /// use anubhav_lang::lang::{Lexer, Parser};
+    ///
+    /// let source = "REPEAT 2 TIMES DO\nBREAK\nPRINT \"a\"\nPRINT \"b\"\nEND";
+    /// let mut parser = Parser::new(Lexer::new(source.to_string()));
+    /// parser.parse().unwrap();
+    /// assert_eq!(
+    ///     parser.warnings(),
+    ///     ["Unreachable statements at lines 3-4 (after BREAK at line 2)"]
+    /// );
+    /// ```
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Where each statement parsed so far starts. Moving the parsed
+    /// statements keeps them findable; cloning them does not.
+    pub fn take_lines(&mut self) -> StatementLines {
+        std::mem::take(&mut self.lines)
+    }
+
     fn advance(&mut self) {
-        self.current_token = self.lexer.next_token();
+        self.previous_line = self.current_line;
+        (self.current_token, self.current_line) = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => (self.lexer.next_token(), self.lexer.token_line()),
+        };
+    }
+
+    /// The token after the current one, without moving past either.
+    fn peek_token(&mut self) -> &Token {
+        let lexer = &mut self.lexer;
+        &self
+            .peeked
+            .get_or_insert_with(|| (lexer.next_token(), lexer.token_line()))
+            .0
     }
 
     pub fn parse(&mut self) -> Result<Vec<Statement>, String> {
-        let mut statements = Vec::new();
+        self.parse_body(&[])
+    }
 
-        while self.current_token != Token::EOF {
-            match self.current_token {
-                Token::Intent => {
-                    statements.push(self.parse_intent_declaration()?);
-                }
-                Token::Manifest => {
-                    statements.push(self.parse_manifest_call()?);
-                }
-                Token::Calculate => {
-                    statements.push(self.parse_calculate()?);
-                }
-                Token::Store => {
-                    statements.push(self.parse_store()?);
-                }
-                Token::Combine => {
-                    statements.push(self.parse_combine()?);
-                }
-                Token::Repeat => {
-                    statements.push(self.parse_repeat()?);
-                }
-                Token::If => {
-                    statements.push(self.parse_if()?);
-                }
-                Token::Print => {
-                    statements.push(self.parse_print()?);
-                }
-                Token::While => {
-                    statements.push(self.parse_while()?);
-                }
-                Token::Increment => {
-                    statements.push(self.parse_increment()?);
-                }
-                Token::Decrement => {
-                    statements.push(self.parse_decrement()?);
-                }
-                Token::For => {
-                    statements.push(self.parse_for()?);
-                }
-                Token::Assert => {
-                    statements.push(self.parse_assert()?);
-                }
-                Token::Try => {
-                    statements.push(self.parse_try_catch()?);
-                }
-                Token::Uppercase | Token::Lowercase => {
-                    statements.push(self.parse_string_transform()?);
-                }
-                Token::Switch => {
-                    statements.push(self.parse_switch()?);
-                }
-                Token::Array => {
-                    statements.push(self.parse_array_create()?);
-                }
-                Token::Push => {
-                    statements.push(self.parse_array_push()?);
-                }
-                Token::Pop => {
-                    statements.push(self.parse_array_pop()?);
-                }
-                Token::Get => {
-                    statements.push(self.parse_array_get()?);
-                }
-                Token::Set => {
-                    statements.push(self.parse_array_set()?);
-                }
-                Token::Size => {
-                    statements.push(self.parse_array_size()?);
-                }
-                Token::Import => {
-                    statements.push(self.parse_import()?);
-                }
-                Token::Export => {
-                    statements.push(self.parse_export()?);
-                }
-                Token::Break => {
-                    self.advance();
-                    statements.push(Statement::Break);
-                }
-                Token::Continue => {
-                    self.advance();
-                    statements.push(Statement::Continue);
-                }
-                Token::Function => {
-                    statements.push(self.parse_function_definition()?);
-                }
-                Token::Call => {
-                    statements.push(self.parse_function_call()?);
-                }
-                Token::Return => {
-                    statements.push(self.parse_return()?);
-                }
-                Token::Sort => {
-                    statements.push(self.parse_array_sort()?);
-                }
-                Token::Filter => {
-                    statements.push(self.parse_array_filter()?);
-                }
-                Token::Reverse => {
-                    statements.push(self.parse_array_reverse()?);
-                }
-                Token::Map => {
-                    statements.push(self.parse_array_map()?);
-                }
-                Token::Sum => {
-                    statements.push(self.parse_array_sum()?);
-                }
-                Token::Join => {
-                    statements.push(self.parse_array_join()?);
-                }
-                Token::Dict => {
-                    statements.push(self.parse_dict_create()?);
-                }
-                Token::Put => {
-                    statements.push(self.parse_dict_put()?);
-                }
-                Token::Fetch => {
-                    statements.push(self.parse_dict_fetch()?);
-                }
-                Token::Keys => {
-                    statements.push(self.parse_dict_keys()?);
-                }
-                Token::Values => {
-                    statements.push(self.parse_dict_values()?);
-                }
-                Token::Delete => {
-                    statements.push(self.parse_dict_delete()?);
-                }
-                Token::RangeOp => {
-                    statements.push(self.parse_range()?);
-                }
-                Token::Unique => {
-                    statements.push(self.parse_unique()?);
-                }
-                Token::Concat => {
-                    statements.push(self.parse_concat()?);
-                }
-                Token::TakeOp => {
-                    statements.push(self.parse_take()?);
-                }
-                Token::DropOp => {
-                    statements.push(self.parse_drop()?);
-                }
-                Token::FindOp => {
-                    statements.push(self.parse_find()?);
-                }
-                Token::AverageOp => {
-                    statements.push(self.parse_average()?);
-                }
-                Token::ClearOp => {
-                    statements.push(self.parse_clear()?);
-                }
-                Token::Shuffle => {
-                    statements.push(self.parse_shuffle()?);
-                }
-                Token::CloneOp => {
-                    statements.push(self.parse_clone()?);
-                }
-                Token::ReadFile => {
-                    statements.push(self.parse_read_file()?);
-                }
-                Token::WriteFile => {
-                    statements.push(self.parse_write_file()?);
-                }
-                Token::AppendFile => {
-                    statements.push(self.parse_append_file()?);
-                }
-                Token::Exists => {
-                    statements.push(self.parse_file_exists()?);
-                }
-                Token::Sleep => {
-                    statements.push(self.parse_sleep()?);
-                }
-                Token::Input => {
-                    statements.push(self.parse_input()?);
-                }
-                Token::Type => {
-                    statements.push(self.parse_get_type()?);
-                }
-                Token::Parse => {
-                    statements.push(self.parse_parse_number()?);
-                }
-                Token::Fold => {
-                    statements.push(self.parse_fold()?);
-                }
-                Token::Zip => {
-                    statements.push(self.parse_zip()?);
-                }
-                Token::Flatten => {
-                    statements.push(self.parse_flatten()?);
-                }
-                Token::CountOp => {
-                    statements.push(self.parse_count()?);
-                }
-                Token::ReplaceOp => {
-                    statements.push(self.parse_replace()?);
-                }
-                Token::SplitOp => {
-                    statements.push(self.parse_split()?);
-                }
-                _ => {
-                    return Err(format!("Unexpected token: {:?}", self.current_token));
+    /// Like `parse`, but keeps going after a statement fails to parse so every
+    /// error in the program is reported at once. Each error is prefixed with
+    /// the line its statement starts on; statements that did parse are
+    /// returned too.
+    /// Code certain to fail when it runs, such as `5 / 0`, is added to the
+    /// warnings, since a TRY may be expecting the failure.
+    ///
+    /// ```
+    /// use anubhav_lang::lang::{Lexer, Parser};
+    ///
+    /// let mut parser = Parser::new(Lexer::new("CALCULATE x 5 / 0".to_string()));
+    /// let (_, errors) = parser.parse_all_errors();
+    /// assert!(errors.is_empty());
+    /// assert_eq!(
+    ///     parser.warnings(),
+    ///     ["Division by literal zero in `5 / 0` at line 1"]
+    /// );
+    ///
+    /// // A statement that runs into the next one leaves it to be checked too
+    /// let source = "STORE x\nFUNCTION f( DO\nEND".to_string();
+    /// let (_, errors) = Parser::new(Lexer::new(source)).parse_all_errors();
+    /// assert_eq!(
+    ///     errors,
+    ///     [
+    ///         "line 1: Unexpected token in expression: FUNCTION",
+    ///         "line 2: Expected parameter name",
+    ///     ]
+    /// );
+    /// ```
+    pub fn parse_all_errors(&mut self) -> (Vec<Statement>, Vec<String>) {
+        self.recovering = true;
+        let statements = self.parse_body(&[]).unwrap_or_default();
+        self.recovering = false;
+        self.warnings.append(&mut self.analysis);
+        (statements, std::mem::take(&mut self.errors))
+    }
+
+    /// Skips past a statement that began on `start_line` and failed to
+    /// parse, up to the first keyword on a later line that can begin a
+    /// statement, or to one of the enclosing block's `terminators`. The
+    /// keyword is left for the next statement, which may be the token the
+    /// failed one stopped at.
+    fn recover(&mut self, start_line: usize, terminators: &[Token]) {
+        while self.current_token != Token::EOF && !terminators.contains(&self.current_token) {
+            let continues_block = matches!(
+                self.current_token,
+                Token::End
+                    | Token::Else
+                    | Token::Catch
+                    | Token::Case
+                    | Token::Default
+                    | Token::When
+            );
+            if self.current_line > start_line
+                && !continues_block
+                && keyword_text(&self.current_token).is_some()
+            {
+                return;
+            }
+            self.advance();
+        }
+    }
+
+    /// Parses a block body up to and including its closing END.
+    fn parse_block(&mut self, construct: &str) -> Result<Vec<Statement>, String> {
+        let body = self.parse_body(&[Token::End])?;
+        if self.current_token == Token::EOF {
+            return Err(format!("Expected END to close {construct}"));
+        }
+        self.advance(); // Skip END
+
+        Ok(body)
+    }
+
+    /// Parses statements until one of `terminators` (left unconsumed) or EOF.
+    /// Warns once about all the statements following an unconditional
+    /// RETURN, EXIT, BREAK or CONTINUE, since none of them can run.
+    fn parse_body(&mut self, terminators: &[Token]) -> Result<Vec<Statement>, String> {
+        let mut body = Vec::new();
+        let mut lines = Vec::new();
+        let mut exit: Option<(&str, usize)> = None;
+        let mut unreachable: Option<(usize, usize, usize)> = None; // First line, last line, count
+
+        loop {
+            self.skip_terminators();
+            if terminators.contains(&self.current_token) || self.current_token == Token::EOF {
+                break;
+            }
+            let line = self.current_line;
+            // After an error, an END at the top level belongs to a block
+            // whose opening statement failed to parse.
+            if self.recovering
+                && terminators.is_empty()
+                && self.current_token == Token::End
+                && !self.errors.is_empty()
+            {
+                self.advance();
+                continue;
+            }
+            // Text the lexer could not read explains the failure better than
+            // whatever the statement expected in its place
+            let result = self
+                .parse_statement()
+                .and_then(|statement| {
+                    self.expect_statement_end(terminators)?;
+                    Ok(statement)
+                })
+                .map_err(|e| match &self.current_token {
+                    Token::Invalid(reason) => reason.clone(),
+                    _ => e,
+                });
+            let statement = match result {
+                Ok(statement) => statement,
+                Err(e) if self.recovering => {
+                    self.errors.push(format!("line {line}: {e}"));
+                    self.recover(line, terminators);
+                    continue;
                 }
+                Err(e) => return Err(e),
+            };
+            if exit.is_some() {
+                let (first, _, count) = unreachable.unwrap_or((line, line, 0));
+                unreachable = Some((first, self.previous_line, count + 1));
+            } else {
+                exit = match statement {
+                    Statement::Return { .. } => Some(("RETURN", line)),
+                    Statement::Exit { .. } => Some(("EXIT", line)),
+                    Statement::Break { .. } => Some(("BREAK", line)),
+                    Statement::Continue { .. } => Some(("CONTINUE", line)),
+                    _ => None,
+                };
+            }
+            body.push(statement);
+            lines.push(line);
+        }
+
+        if let (Some((keyword, exit_line)), Some((first, last, count))) = (exit, unreachable) {
+            let statements = if count == 1 {
+                "statement"
+            } else {
+                "statements"
+            };
+            let lines = if first == last {
+                format!("line {first}")
+            } else {
+                format!("lines {first}-{last}")
+            };
+            self.warnings.push(format!(
+                "Unreachable {statements} at {lines} (after {keyword} at line {exit_line})"
+            ));
+        }
+
+        // The body is complete, so its statements stay where they are now
+        for (statement, line) in body.iter().zip(lines) {
+            self.lines.0.insert(statement, line);
+        }
+        Ok(body)
+    }
+
+    /// Skips blank lines and stray `;` when terminators are on.
+    fn skip_terminators(&mut self) {
+        while self.current_token == Token::Terminator {
+            self.advance();
+        }
+    }
+
+    /// With terminators on, a statement must be followed by a newline, `;`,
+    /// the end of its block or the end of the program.
+    fn expect_statement_end(&self, terminators: &[Token]) -> Result<(), String> {
+        if !self.lexer.terminators()
+            || matches!(self.current_token, Token::Terminator | Token::EOF)
+            || terminators.contains(&self.current_token)
+        {
+            return Ok(());
+        }
+        Err(format!(
+            "Expected a newline or ; to end the statement, found {}",
+            self.current_token
+        ))
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, String> {
+        // A name followed by `.` starts an array method chain: `nums.sort()`
+        if matches!(self.current_token, Token::Identifier(_)) && *self.peek_token() == Token::Dot {
+            return self.parse_array_chain();
+        }
+
+        match self.current_token {
+            Token::Intent => self.parse_intent_declaration(),
+            Token::Manifest => self.parse_manifest_call(),
+            Token::Calculate => self.parse_calculate(),
+            Token::Store => self.parse_store(),
+            Token::Combine => self.parse_combine(),
+            Token::Repeat => self.parse_repeat(),
+            Token::If => self.parse_if(),
+            Token::Print => self.parse_print(),
+            Token::While => self.parse_while(),
+            Token::Increment => self.parse_increment(),
+            Token::Decrement => self.parse_decrement(),
+            Token::For => self.parse_for(),
+            Token::Assert => self.parse_assert(),
+            Token::Try => self.parse_try_catch(),
+            Token::Uppercase
+            | Token::Lowercase
+            | Token::Capitalize
+            | Token::TitleCase
+            | Token::NormalizeWs => self.parse_string_transform(),
+            Token::Switch => self.parse_switch(),
+            Token::Array => self.parse_array_create(),
+            Token::Push => self.parse_array_push(),
+            Token::Pop => self.parse_array_pop(),
+            Token::Get => self.parse_array_get(),
+            Token::Set => self.parse_array_set(),
+            Token::Size => self.parse_array_size(),
+            Token::Import => self.parse_import(),
+            Token::Export => self.parse_export(),
+            Token::Break | Token::Continue => self.parse_loop_control(),
+            Token::Function => self.parse_function_definition(),
+            Token::Call => self.parse_function_call(),
+            Token::Return => self.parse_return(),
+            Token::Sort => self.parse_array_sort(),
+            Token::Filter => self.parse_array_filter(),
+            Token::Reverse => self.parse_array_reverse(),
+            Token::Map => self.parse_array_map(),
+            Token::Sum => self.parse_array_sum(),
+            Token::Join => self.parse_array_join(),
+            Token::Dict => self.parse_dict_create(),
+            Token::Put => self.parse_dict_put(),
+            Token::Fetch => self.parse_dict_fetch(),
+            Token::Keys => self.parse_dict_keys(),
+            Token::Values => self.parse_dict_values(),
+            Token::Delete => self.parse_dict_delete(),
+            Token::RangeOp => self.parse_range(),
+            Token::Unique => self.parse_unique(),
+            Token::Concat => self.parse_concat(),
+            Token::TakeOp => self.parse_take(),
+            Token::DropOp => self.parse_drop(),
+            Token::FindOp => self.parse_find(),
+            Token::AverageOp => self.parse_average(),
+            Token::ClearOp => self.parse_clear(),
+            Token::Shuffle => self.parse_shuffle(),
+            Token::CloneOp => self.parse_clone(),
+            Token::ReadFile => self.parse_read_file(),
+            Token::WriteFile => self.parse_write_file(),
+            Token::AppendFile => self.parse_append_file(),
+            Token::Exists => self.parse_file_exists(),
+            Token::Sleep | Token::SleepSec => self.parse_sleep(),
+            Token::Input | Token::InputStr | Token::InputNum => self.parse_input(),
+            Token::Type => self.parse_get_type(),
+            Token::Parse => self.parse_parse_number(),
+            Token::Fold => self.parse_fold(),
+            Token::Zip => self.parse_zip(),
+            Token::Flatten => self.parse_flatten(),
+            Token::CountOp => self.parse_count(),
+            Token::ReplaceOp => self.parse_replace(),
+            Token::SplitOp => self.parse_split(),
+            Token::DictSize => self.parse_dict_size(),
+            Token::Record => self.parse_record(),
+            Token::Field => self.parse_field(),
+            Token::Scope => self.parse_scope(),
+            Token::Histogram => self.parse_histogram(),
+            Token::PrintTable => self.parse_print_table(),
+            Token::FnRef => self.parse_fn_ref(),
+            Token::CallDynamic => self.parse_dynamic_call(),
+            Token::AssertNear => self.parse_assert_near(),
+            Token::Unless => self.parse_unless(),
+            Token::ReadLines => self.parse_read_lines(),
+            Token::WriteLines => self.parse_write_lines(),
+            Token::Extend => self.parse_extend(),
+            Token::ListDir => self.parse_list_dir(),
+            Token::FileSize => self.parse_file_size(),
+            Token::DeleteFile => self.parse_delete_file(),
+            Token::RenameFile => self.parse_rename_file(),
+            Token::FormatThousands => self.parse_format_thousands(),
+            Token::FormatSci => self.parse_format_sci(),
+            Token::ArrayEq | Token::DictEq | Token::ArrayCmp => self.parse_equality_check(),
+            Token::Cond => self.parse_cond(),
+            Token::SetGrow => self.parse_array_set(),
+            Token::ForRange => self.parse_for_range(),
+            Token::Enumerate => self.parse_enumerate(),
+            Token::SliceStr => self.parse_slice_str(),
+            Token::Includes => self.parse_includes(),
+            Token::MapDict | Token::FilterDict => self.parse_dict_transform(),
+            Token::CountStr => self.parse_count_str(),
+            Token::Flush => {
+                self.advance(); // Skip FLUSH
+                Ok(Statement::Flush)
             }
+            Token::ParseRadix => self.parse_parse_radix(),
+            Token::ToRadix => self.parse_to_radix(),
+            Token::FormatDate => self.parse_format_date(),
+            Token::DictGetOr => self.parse_dict_get_or(),
+            Token::Exit => self.parse_exit(),
+            Token::ZipWith => self.parse_zip_with(),
+            Token::Collect => self.parse_collect(),
+            Token::Use => self.parse_use(),
+            Token::Log => self.parse_log(),
+            Token::Format => self.parse_format(),
+            Token::ArrayToDict | Token::DictToArray => self.parse_container_conversion(),
+            Token::RequireKeys => self.parse_require_keys(),
+            Token::ParseJson => self.parse_parse_json(),
+            Token::SerializeJson => self.parse_serialize_json(),
+            Token::SumNumeric => self.parse_sum_numeric(),
+            Token::MinOfOp | Token::MaxOfOp | Token::MinOfOr | Token::MaxOfOr => {
+                self.parse_array_extreme()
+            }
+            Token::Stats => self.parse_stats(),
+            Token::SetLogLevel => {
+                self.advance();
+                let level = self.parse_log_level()?;
+                Ok(Statement::SetLogLevel { level })
+            }
+            Token::DumpState => {
+                self.advance();
+                Ok(Statement::DumpState)
+            }
+            _ => Err(format!("Unexpected token: {}", self.current_token)),
+        }
+    }
+
+    fn parse_loop_control(&mut self) -> Result<Statement, String> {
+        let keyword = if self.current_token == Token::Break {
+            "BREAK"
+        } else {
+            "CONTINUE"
+        };
+        let line = self.current_line;
+        if self.loops.is_empty() {
+            return Err(format!("{keyword} outside of loop at line {line}"));
         }
+        self.advance(); // Skip BREAK/CONTINUE
+
+        let label = if let Token::Label(name) = &self.current_token {
+            let name = name.clone();
+            if !self
+                .loops
+                .iter()
+                .any(|l| l.as_deref() == Some(name.as_str()))
+            {
+                return Err(format!(
+                    "{keyword} @{name} does not name an enclosing loop at line {line}"
+                ));
+            }
+            self.advance();
+            Some(name)
+        } else {
+            None
+        };
+
+        Ok(if keyword == "BREAK" {
+            Statement::Break { label }
+        } else {
+            Statement::Continue { label }
+        })
+    }
+
+    /// Parses a loop body after DO, with its optional `@label`, up to (not including) END.
+    fn parse_loop_body(&mut self) -> Result<(Vec<Statement>, Option<String>), String> {
+        let label = if let Token::Label(name) = &self.current_token {
+            let name = name.clone();
+            self.advance();
+            Some(name)
+        } else {
+            None
+        };
 
-        Ok(statements)
+        self.loops.push(label.clone());
+        let body = self.parse_body(&[Token::End]);
+        self.loops.pop();
+
+        Ok((body?, label))
     }
 
     fn parse_intent_declaration(&mut self) -> Result<Statement, String> {
@@ -591,7 +1314,10 @@ impl Parser {
 
         let value = self.parse_expression()?;
 
-        Ok(Statement::Store { name, value })
+        Ok(Statement::Store {
+            name: Symbol::intern(&name),
+            value,
+        })
     }
 
     fn parse_combine(&mut self) -> Result<Statement, String> {
@@ -606,31 +1332,55 @@ impl Parser {
 
         let mut parts = Vec::new();
 
-        // Parse string literals and identifiers
-        while matches!(
-            self.current_token,
-            Token::StringLiteral(_) | Token::Identifier(_)
-        ) {
+        // Parse string literals, identifiers and expressions
+        loop {
+            if matches!(
+                self.current_token,
+                Token::StringLiteral(_) | Token::Identifier(_)
+            ) && *self.peek_token() == Token::Star
+            {
+                parts.push(CombinePart::Expression(self.parse_combine_expression()?));
+                continue;
+            }
             match &self.current_token {
                 Token::StringLiteral(s) => {
-                    parts.push(s.clone());
+                    let part = match s.strip_prefix("${").and_then(|s| s.strip_suffix('}')) {
+                        Some(var_name) => CombinePart::Variable(var_name.to_string()),
+                        None => CombinePart::Literal(s.clone()),
+                    };
                     self.advance();
+                    parts.push(part);
                 }
                 Token::Identifier(id) => {
-                    parts.push(format!("${{{id}}}"));
+                    parts.push(CombinePart::Variable(id.clone()));
                     self.advance();
                 }
+                Token::LeftParen | Token::Recall | Token::Number(_) => {
+                    parts.push(CombinePart::Expression(self.parse_expression()?));
+                }
                 _ => break,
             }
         }
 
         if parts.is_empty() {
-            return Err("Expected strings or identifiers after COMBINE name".to_string());
+            return Err(
+                "Expected strings, identifiers or expressions after COMBINE name".to_string(),
+            );
         }
 
         Ok(Statement::Combine { name, parts })
     }
 
+    /// A COMBINE part starting with a literal or name followed by `*`, parsed as
+    /// one expression with bare names read as RECALL: `x * 2 + 1` is 11 when x
+    /// is 5. Whether a name holds text, and so repeats, is only known when it runs.
+    fn parse_combine_expression(&mut self) -> Result<Expression, String> {
+        self.bare_names = true;
+        let expression = self.parse_expression();
+        self.bare_names = false;
+        expression
+    }
+
     fn parse_repeat(&mut self) -> Result<Statement, String> {
         self.advance(); // Skip REPEAT
 
@@ -646,39 +1396,14 @@ impl Parser {
         }
         self.advance(); // Skip DO
 
-        let mut body = Vec::new();
-
-        while self.current_token != Token::End && self.current_token != Token::EOF {
-            match self.current_token {
-                Token::Intent => body.push(self.parse_intent_declaration()?),
-                Token::Manifest => body.push(self.parse_manifest_call()?),
-                Token::Calculate => body.push(self.parse_calculate()?),
-                Token::Store => body.push(self.parse_store()?),
-                Token::Combine => body.push(self.parse_combine()?),
-                Token::Print => body.push(self.parse_print()?),
-                Token::Break => {
-                    self.advance();
-                    body.push(Statement::Break);
-                }
-                Token::Continue => {
-                    self.advance();
-                    body.push(Statement::Continue);
-                }
-                _ => {
-                    return Err(format!(
-                        "Unexpected token in repeat body: {:?}",
-                        self.current_token
-                    ));
-                }
-            }
-        }
+        let (body, label) = self.parse_loop_body()?;
 
         if self.current_token != Token::End {
             return Err("Expected END to close REPEAT".to_string());
         }
         self.advance(); // Skip END
 
-        Ok(Statement::Repeat { count, body })
+        Ok(Statement::Repeat { count, body, label })
     }
 
     fn parse_if(&mut self) -> Result<Statement, String> {
@@ -691,67 +1416,11 @@ impl Parser {
         }
         self.advance(); // Skip THEN
 
-        let mut then_body = Vec::new();
-
-        while self.current_token != Token::Else
-            && self.current_token != Token::End
-            && self.current_token != Token::EOF
-        {
-            match self.current_token {
-                Token::Intent => then_body.push(self.parse_intent_declaration()?),
-                Token::Manifest => then_body.push(self.parse_manifest_call()?),
-                Token::Calculate => then_body.push(self.parse_calculate()?),
-                Token::Store => then_body.push(self.parse_store()?),
-                Token::Combine => then_body.push(self.parse_combine()?),
-                Token::Print => then_body.push(self.parse_print()?),
-                Token::Break => {
-                    self.advance();
-                    then_body.push(Statement::Break);
-                }
-                Token::Continue => {
-                    self.advance();
-                    then_body.push(Statement::Continue);
-                }
-                Token::Return => then_body.push(self.parse_return()?),
-                _ => {
-                    return Err(format!(
-                        "Unexpected token in IF body: {:?}",
-                        self.current_token
-                    ));
-                }
-            }
-        }
+        let then_body = self.parse_body(&[Token::Else, Token::End])?;
 
         let else_body = if self.current_token == Token::Else {
             self.advance(); // Skip ELSE
-            let mut else_stmts = Vec::new();
-
-            while self.current_token != Token::End && self.current_token != Token::EOF {
-                match self.current_token {
-                    Token::Intent => else_stmts.push(self.parse_intent_declaration()?),
-                    Token::Manifest => else_stmts.push(self.parse_manifest_call()?),
-                    Token::Calculate => else_stmts.push(self.parse_calculate()?),
-                    Token::Store => else_stmts.push(self.parse_store()?),
-                    Token::Combine => else_stmts.push(self.parse_combine()?),
-                    Token::If => else_stmts.push(self.parse_if()?),
-                    Token::Print => else_stmts.push(self.parse_print()?),
-                    Token::Break => {
-                        self.advance();
-                        else_stmts.push(Statement::Break);
-                    }
-                    Token::Continue => {
-                        self.advance();
-                        else_stmts.push(Statement::Continue);
-                    }
-                    Token::Return => else_stmts.push(self.parse_return()?),
-                    _ => {
-                        return Err(format!(
-                            "Unexpected token in ELSE body: {:?}",
-                            self.current_token
-                        ));
-                    }
-                }
-            }
+            let else_stmts = self.parse_body(&[Token::End])?;
             Some(else_stmts)
         } else {
             None
@@ -809,42 +1478,18 @@ impl Parser {
         }
         self.advance(); // Skip DO
 
-        let mut body = Vec::new();
-
-        while self.current_token != Token::End && self.current_token != Token::EOF {
-            match self.current_token {
-                Token::Intent => body.push(self.parse_intent_declaration()?),
-                Token::Manifest => body.push(self.parse_manifest_call()?),
-                Token::Calculate => body.push(self.parse_calculate()?),
-                Token::Store => body.push(self.parse_store()?),
-                Token::Combine => body.push(self.parse_combine()?),
-                Token::Print => body.push(self.parse_print()?),
-                Token::If => body.push(self.parse_if()?),
-                Token::Increment => body.push(self.parse_increment()?),
-                Token::Decrement => body.push(self.parse_decrement()?),
-                Token::Break => {
-                    self.advance();
-                    body.push(Statement::Break);
-                }
-                Token::Continue => {
-                    self.advance();
-                    body.push(Statement::Continue);
-                }
-                _ => {
-                    return Err(format!(
-                        "Unexpected token in WHILE body: {:?}",
-                        self.current_token
-                    ));
-                }
-            }
-        }
+        let (body, label) = self.parse_loop_body()?;
 
         if self.current_token != Token::End {
             return Err("Expected END to close WHILE".to_string());
         }
         self.advance(); // Skip END
 
-        Ok(Statement::While { condition, body })
+        Ok(Statement::While {
+            condition,
+            body,
+            label,
+        })
     }
 
     fn parse_increment(&mut self) -> Result<Statement, String> {
@@ -857,7 +1502,9 @@ impl Parser {
         };
         self.advance();
 
-        Ok(Statement::Increment { variable })
+        Ok(Statement::Increment {
+            variable: Symbol::intern(&variable),
+        })
     }
 
     fn parse_decrement(&mut self) -> Result<Statement, String> {
@@ -870,7 +1517,9 @@ impl Parser {
         };
         self.advance();
 
-        Ok(Statement::Decrement { variable })
+        Ok(Statement::Decrement {
+            variable: Symbol::intern(&variable),
+        })
     }
 
     fn parse_for(&mut self) -> Result<Statement, String> {
@@ -904,41 +1553,7 @@ impl Parser {
         }
         self.advance(); // Skip DO
 
-        let mut body = Vec::new();
-
-        while self.current_token != Token::End && self.current_token != Token::EOF {
-            match self.current_token {
-                Token::Intent => body.push(self.parse_intent_declaration()?),
-                Token::Manifest => body.push(self.parse_manifest_call()?),
-                Token::Calculate => body.push(self.parse_calculate()?),
-                Token::Store => body.push(self.parse_store()?),
-                Token::Combine => body.push(self.parse_combine()?),
-                Token::Print => body.push(self.parse_print()?),
-                Token::If => body.push(self.parse_if()?),
-                Token::Increment => body.push(self.parse_increment()?),
-                Token::Decrement => body.push(self.parse_decrement()?),
-                Token::For => body.push(self.parse_for()?),
-                Token::Array => body.push(self.parse_array_create()?),
-                Token::Push => body.push(self.parse_array_push()?),
-                Token::Pop => body.push(self.parse_array_pop()?),
-                Token::Get => body.push(self.parse_array_get()?),
-                Token::Set => body.push(self.parse_array_set()?),
-                Token::Break => {
-                    self.advance();
-                    body.push(Statement::Break);
-                }
-                Token::Continue => {
-                    self.advance();
-                    body.push(Statement::Continue);
-                }
-                _ => {
-                    return Err(format!(
-                        "Unexpected token in FOR body: {:?}",
-                        self.current_token
-                    ));
-                }
-            }
-        }
+        let (body, label) = self.parse_loop_body()?;
 
         if self.current_token != Token::End {
             return Err("Expected END to close FOR".to_string());
@@ -946,11 +1561,12 @@ impl Parser {
         self.advance(); // Skip END
 
         Ok(Statement::For {
-            variable,
+            variable: Symbol::intern(&variable),
             start,
             end,
             step,
             body,
+            label,
         })
     }
 
@@ -977,60 +1593,16 @@ impl Parser {
     fn parse_try_catch(&mut self) -> Result<Statement, String> {
         self.advance(); // Skip TRY
 
-        let mut try_body = Vec::new();
-
-        while self.current_token != Token::Catch {
-            match self.current_token {
-                Token::EOF => return Err("Expected CATCH after TRY".to_string()),
-                Token::Intent => try_body.push(self.parse_intent_declaration()?),
-                Token::Manifest => try_body.push(self.parse_manifest_call()?),
-                Token::Calculate => try_body.push(self.parse_calculate()?),
-                Token::Store => try_body.push(self.parse_store()?),
-                Token::Combine => try_body.push(self.parse_combine()?),
-                Token::Repeat => try_body.push(self.parse_repeat()?),
-                Token::If => try_body.push(self.parse_if()?),
-                Token::Print => try_body.push(self.parse_print()?),
-                Token::While => try_body.push(self.parse_while()?),
-                Token::Increment => try_body.push(self.parse_increment()?),
-                Token::Decrement => try_body.push(self.parse_decrement()?),
-                Token::For => try_body.push(self.parse_for()?),
-                Token::Assert => try_body.push(self.parse_assert()?),
-                _ => {
-                    return Err(format!(
-                        "Unexpected token in TRY body: {:?}",
-                        self.current_token
-                    ));
-                }
-            }
+        let try_body = self.parse_body(&[Token::Catch])?;
+        if self.current_token == Token::EOF {
+            return Err("Expected CATCH after TRY".to_string());
         }
 
         self.advance(); // Skip CATCH
 
-        let mut catch_body = Vec::new();
-
-        while self.current_token != Token::End {
-            match self.current_token {
-                Token::EOF => return Err("Expected END after CATCH".to_string()),
-                Token::Intent => catch_body.push(self.parse_intent_declaration()?),
-                Token::Manifest => catch_body.push(self.parse_manifest_call()?),
-                Token::Calculate => catch_body.push(self.parse_calculate()?),
-                Token::Store => catch_body.push(self.parse_store()?),
-                Token::Combine => catch_body.push(self.parse_combine()?),
-                Token::Repeat => catch_body.push(self.parse_repeat()?),
-                Token::If => catch_body.push(self.parse_if()?),
-                Token::Print => catch_body.push(self.parse_print()?),
-                Token::While => catch_body.push(self.parse_while()?),
-                Token::Increment => catch_body.push(self.parse_increment()?),
-                Token::Decrement => catch_body.push(self.parse_decrement()?),
-                Token::For => catch_body.push(self.parse_for()?),
-                Token::Assert => catch_body.push(self.parse_assert()?),
-                _ => {
-                    return Err(format!(
-                        "Unexpected token in CATCH body: {:?}",
-                        self.current_token
-                    ));
-                }
-            }
+        let catch_body = self.parse_body(&[Token::End])?;
+        if self.current_token == Token::EOF {
+            return Err("Expected END after CATCH".to_string());
         }
 
         if self.current_token != Token::End {
@@ -1048,6 +1620,9 @@ impl Parser {
         let operation = match self.current_token {
             Token::Uppercase => "UPPERCASE".to_string(),
             Token::Lowercase => "LOWERCASE".to_string(),
+            Token::Capitalize => "CAPITALIZE".to_string(),
+            Token::TitleCase => "TITLE_CASE".to_string(),
+            Token::NormalizeWs => "NORMALIZE_WS".to_string(),
             _ => return Err("Invalid string operation".to_string()),
         };
         self.advance(); // Skip operation token
@@ -1092,6 +1667,7 @@ impl Parser {
         let mut cases = Vec::new();
         let mut default_case = None;
 
+        self.skip_terminators();
         while self.current_token != Token::End {
             match self.current_token {
                 Token::Case => {
@@ -1104,36 +1680,9 @@ impl Parser {
                     }
                     self.advance(); // Skip DO
 
-                    let mut case_body = Vec::new();
-                    while self.current_token != Token::Case
-                        && self.current_token != Token::Default
-                        && self.current_token != Token::End
-                    {
-                        match self.current_token {
-                            Token::EOF => {
-                                return Err("Expected CASE, DEFAULT, or END in SWITCH".to_string());
-                            }
-                            Token::Intent => case_body.push(self.parse_intent_declaration()?),
-                            Token::Manifest => case_body.push(self.parse_manifest_call()?),
-                            Token::Calculate => case_body.push(self.parse_calculate()?),
-                            Token::Store => case_body.push(self.parse_store()?),
-                            Token::Combine => case_body.push(self.parse_combine()?),
-                            Token::Repeat => case_body.push(self.parse_repeat()?),
-                            Token::If => case_body.push(self.parse_if()?),
-                            Token::Print => case_body.push(self.parse_print()?),
-                            Token::While => case_body.push(self.parse_while()?),
-                            Token::Increment => case_body.push(self.parse_increment()?),
-                            Token::Decrement => case_body.push(self.parse_decrement()?),
-                            Token::For => case_body.push(self.parse_for()?),
-                            Token::Assert => case_body.push(self.parse_assert()?),
-                            Token::Switch => case_body.push(self.parse_switch()?),
-                            _ => {
-                                return Err(format!(
-                                    "Unexpected token in CASE body: {:?}",
-                                    self.current_token
-                                ));
-                            }
-                        }
+                    let case_body = self.parse_body(&[Token::Case, Token::Default, Token::End])?;
+                    if self.current_token == Token::EOF {
+                        return Err("Expected CASE, DEFAULT, or END in SWITCH".to_string());
                     }
 
                     cases.push((case_value, case_body));
@@ -1146,31 +1695,9 @@ impl Parser {
                     }
                     self.advance(); // Skip DO
 
-                    let mut default_body = Vec::new();
-                    while self.current_token != Token::End {
-                        match self.current_token {
-                            Token::EOF => return Err("Expected END after DEFAULT".to_string()),
-                            Token::Intent => default_body.push(self.parse_intent_declaration()?),
-                            Token::Manifest => default_body.push(self.parse_manifest_call()?),
-                            Token::Calculate => default_body.push(self.parse_calculate()?),
-                            Token::Store => default_body.push(self.parse_store()?),
-                            Token::Combine => default_body.push(self.parse_combine()?),
-                            Token::Repeat => default_body.push(self.parse_repeat()?),
-                            Token::If => default_body.push(self.parse_if()?),
-                            Token::Print => default_body.push(self.parse_print()?),
-                            Token::While => default_body.push(self.parse_while()?),
-                            Token::Increment => default_body.push(self.parse_increment()?),
-                            Token::Decrement => default_body.push(self.parse_decrement()?),
-                            Token::For => default_body.push(self.parse_for()?),
-                            Token::Assert => default_body.push(self.parse_assert()?),
-                            Token::Switch => default_body.push(self.parse_switch()?),
-                            _ => {
-                                return Err(format!(
-                                    "Unexpected token in DEFAULT body: {:?}",
-                                    self.current_token
-                                ));
-                            }
-                        }
+                    let default_body = self.parse_body(&[Token::End])?;
+                    if self.current_token == Token::EOF {
+                        return Err("Expected END after DEFAULT".to_string());
                     }
 
                     default_case = Some(default_body);
@@ -1178,7 +1705,7 @@ impl Parser {
                 }
                 _ => {
                     return Err(format!(
-                        "Expected CASE or DEFAULT in SWITCH: {:?}",
+                        "Expected CASE or DEFAULT in SWITCH: {}",
                         self.current_token
                     ));
                 }
@@ -1298,12 +1825,13 @@ impl Parser {
     }
 
     fn parse_array_set(&mut self) -> Result<Statement, String> {
-        self.advance(); // Skip SET
+        let keyword = self.current_token.clone();
+        self.advance(); // Skip SET / SET_GROW
 
         let array_name = if let Token::Identifier(name) = &self.current_token {
             name.clone()
         } else {
-            return Err("Expected array name after SET".to_string());
+            return Err(format!("Expected array name after {keyword}"));
         };
         self.advance();
 
@@ -1311,11 +1839,19 @@ impl Parser {
 
         let value = self.parse_expression()?;
 
-        Ok(Statement::ArraySet {
-            array_name,
-            index,
-            value,
-        })
+        if keyword == Token::SetGrow {
+            Ok(Statement::ArraySetGrow {
+                array_name,
+                index,
+                value,
+            })
+        } else {
+            Ok(Statement::ArraySet {
+                array_name,
+                index,
+                value,
+            })
+        }
     }
 
     fn parse_import(&mut self) -> Result<Statement, String> {
@@ -1328,7 +1864,24 @@ impl Parser {
         };
         self.advance();
 
-        Ok(Statement::Import { filename })
+        let namespace = if self.current_token == Token::As {
+            self.advance();
+            match &self.current_token {
+                Token::Identifier(name) => {
+                    let name = name.clone();
+                    self.advance();
+                    Some(name)
+                }
+                _ => return Err("Expected namespace name after AS".to_string()),
+            }
+        } else {
+            None
+        };
+
+        Ok(Statement::Import {
+            filename,
+            namespace,
+        })
     }
 
     fn parse_export(&mut self) -> Result<Statement, String> {
@@ -1447,32 +2000,45 @@ impl Parser {
         ) {
             let operator = self.current_token.clone();
             self.advance();
+            let line = self.current_line;
             let right = self.parse_power()?;
+            // Only a literal zero is certain; a variable could hold anything
+            let zero_divisor = match operator {
+                Token::Slash => Some("Division"),
+                Token::Percent => Some("Modulo"),
+                _ => None,
+            }
+            .filter(|_| matches!(right, Expression::Number(n) if n == 0.0));
             left = Expression::BinaryOp {
                 left: Box::new(left),
                 operator,
                 right: Box::new(right),
             };
+            if let Some(kind) = zero_divisor {
+                self.analysis
+                    .push(format!("{kind} by literal zero in `{left}` at line {line}"));
+            }
         }
 
         Ok(left)
     }
 
+    /// `**` is right-associative: `2 ** 3 ** 2` is `2 ** (3 ** 2)`.
     fn parse_power(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_primary()?;
+        let left = self.parse_primary()?;
 
-        while self.current_token == Token::Power {
-            let operator = self.current_token.clone();
-            self.advance();
-            let right = self.parse_primary()?;
-            left = Expression::BinaryOp {
-                left: Box::new(left),
-                operator,
-                right: Box::new(right),
-            };
+        if self.current_token != Token::Power {
+            return Ok(left);
         }
+        let operator = self.current_token.clone();
+        self.advance();
+        let right = self.parse_power()?;
 
-        Ok(left)
+        Ok(Expression::BinaryOp {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        })
     }
 
     fn parse_primary(&mut self) -> Result<Expression, String> {
@@ -1493,26 +2059,48 @@ impl Parser {
                 self.advance();
                 Ok(Expression::Number(num))
             }
+            Token::StringLiteral(text) => {
+                let expr = match text.strip_prefix("${").and_then(|s| s.strip_suffix('}')) {
+                    Some(name) if self.bare_names => Expression::Recall(Symbol::intern(name)),
+                    _ => Expression::Text(text.clone()),
+                };
+                self.advance();
+                Ok(expr)
+            }
             Token::Min
             | Token::Max
+            | Token::Gcd
+            | Token::Lcm
             | Token::Floor
             | Token::Ceil
             | Token::Round
+            | Token::Abs
+            | Token::Sign
+            | Token::Sqrt
+            | Token::IsInt
+            | Token::IsEven
+            | Token::IsOdd
+            | Token::Trunc
+            | Token::Fract
             | Token::Random
             | Token::Length
-            | Token::Size => {
+            | Token::Size
+            | Token::DictSize => {
                 let op = self.current_token.clone();
                 self.advance();
 
                 if self.current_token != Token::LeftParen {
-                    return Err(format!("Expected ( after {op:?}"));
+                    return Err(format!("Expected ( after {op}"));
                 }
                 self.advance();
 
                 match op {
-                    Token::Min | Token::Max => {
+                    Token::Min | Token::Max | Token::Gcd | Token::Lcm => {
                         // Two-argument functions
                         let first_arg = self.parse_primary()?;
+                        if self.current_token == Token::Comma {
+                            self.advance();
+                        }
                         let second_arg = self.parse_primary()?;
 
                         if self.current_token != Token::RightParen {
@@ -1526,7 +2114,17 @@ impl Parser {
                             right: Box::new(second_arg),
                         })
                     }
-                    Token::Floor | Token::Ceil | Token::Round => {
+                    Token::Floor
+                    | Token::Ceil
+                    | Token::Round
+                    | Token::Abs
+                    | Token::Sign
+                    | Token::Sqrt
+                    | Token::IsInt
+                    | Token::IsEven
+                    | Token::IsOdd
+                    | Token::Trunc
+                    | Token::Fract => {
                         // Single-argument functions
                         let arg = self.parse_primary()?;
 
@@ -1554,10 +2152,10 @@ impl Parser {
                             right: Box::new(Expression::Number(0.0)), // Dummy right operand
                         })
                     }
-                    Token::Size | Token::Length => {
-                        // String function - takes string literal or identifier
+                    Token::Size | Token::Length | Token::DictSize => {
+                        // Size functions - take a string literal or a container name
                         if let Token::StringLiteral(s) = &self.current_token {
-                            let str_len = s.len() as f64;
+                            let str_len = s.chars().count() as f64;
                             self.advance();
 
                             if self.current_token != Token::RightParen {
@@ -1577,12 +2175,12 @@ impl Parser {
 
                             // Store the identifier to be resolved at runtime
                             Ok(Expression::BinaryOp {
-                                left: Box::new(Expression::Recall(var_name)),
+                                left: Box::new(Expression::Recall(Symbol::intern(&var_name))),
                                 operator: op,
                                 right: Box::new(Expression::Number(0.0)), // Dummy
                             })
                         } else {
-                            return Err("LENGTH expects string literal or identifier".to_string());
+                            Err("LENGTH expects string literal or identifier".to_string())
                         }
                     }
                     _ => unreachable!(),
@@ -1593,11 +2191,83 @@ impl Parser {
                 if let Token::Identifier(name) = &self.current_token {
                     let var_name = name.clone();
                     self.advance();
-                    Ok(Expression::Recall(var_name))
+                    Ok(Expression::Recall(Symbol::intern(&var_name)))
                 } else {
                     Err("Expected identifier after RECALL".to_string())
                 }
             }
+            Token::Let => {
+                self.advance(); // Skip LET
+                let Token::Identifier(name) = &self.current_token else {
+                    return Err("Expected identifier after LET".to_string());
+                };
+                let name = name.clone();
+                self.advance();
+                if self.current_token != Token::Equal {
+                    return Err(format!("Expected = after LET {name}"));
+                }
+                self.advance();
+                let value = self.parse_expression()?;
+                if self.current_token != Token::In {
+                    return Err(format!("Expected IN after the value of LET {name}"));
+                }
+                self.advance();
+                // The body extends as far as it can: `LET x = 2 IN RECALL x + 1` is 3
+                let body = self.parse_expression()?;
+                Ok(Expression::Let {
+                    name: Symbol::intern(&name),
+                    value: Box::new(value),
+                    body: Box::new(body),
+                })
+            }
+            Token::Call => {
+                self.advance(); // Skip CALL
+                let function_name =
+                    self.parse_function_name("Expected function name after CALL")?;
+                if self.current_token != Token::LeftParen {
+                    return Err(format!("Expected ( after CALL {function_name}"));
+                }
+                let arguments = self.parse_argument_list()?;
+                Ok(Expression::Call {
+                    function_name,
+                    arguments,
+                })
+            }
+            Token::RecallOr => {
+                self.advance(); // Skip RECALL_OR
+                let Token::Identifier(name) = &self.current_token else {
+                    return Err("Expected identifier after RECALL_OR".to_string());
+                };
+                let name = name.clone();
+                self.advance();
+                // Like a function argument, the default is a single operand
+                let default = self.parse_primary()?;
+                Ok(Expression::RecallOr {
+                    name: Symbol::intern(&name),
+                    default: Box::new(default),
+                })
+            }
+            Token::Identifier(name) => {
+                // A bare name is only valid as an indexed array, arr[i], outside COMBINE
+                let array = name.clone();
+                self.advance();
+                if self.current_token != Token::LeftBracket {
+                    if self.bare_names {
+                        return Ok(Expression::Recall(Symbol::intern(&array)));
+                    }
+                    return Err(format!("Unexpected token in expression: {array}"));
+                }
+                self.advance(); // Skip [
+                let index = self.parse_expression()?;
+                if self.current_token != Token::RightBracket {
+                    return Err(format!("Expected ] after index into '{array}'"));
+                }
+                self.advance(); // Skip ]
+                Ok(Expression::Index {
+                    array,
+                    index: Box::new(index),
+                })
+            }
             Token::Minus => {
                 self.advance(); // Skip minus
                 let expr = self.parse_primary()?;
@@ -1617,7 +2287,7 @@ impl Parser {
                 Ok(expr)
             }
             _ => Err(format!(
-                "Unexpected token in expression: {:?}",
+                "Unexpected token in expression: {}",
                 self.current_token
             )),
         }
@@ -1644,9 +2314,8 @@ impl Parser {
                     parameters.push(param_name.clone());
                     self.advance();
 
-                    // Skip comma if present
-                    if self.current_token == Token::Plus {
-                        // Using + as comma separator
+                    // Skip comma if present (+ is accepted as a separator too)
+                    if matches!(self.current_token, Token::Comma | Token::Plus) {
                         self.advance();
                     }
                 } else {
@@ -1665,28 +2334,13 @@ impl Parser {
         }
         self.advance(); // Skip DO
 
-        let mut body = Vec::new();
-
-        while self.current_token != Token::End && self.current_token != Token::EOF {
-            match self.current_token {
-                Token::Intent => body.push(self.parse_intent_declaration()?),
-                Token::Manifest => body.push(self.parse_manifest_call()?),
-                Token::Calculate => body.push(self.parse_calculate()?),
-                Token::Store => body.push(self.parse_store()?),
-                Token::Print => body.push(self.parse_print()?),
-                Token::If => body.push(self.parse_if()?),
-                Token::While => body.push(self.parse_while()?),
-                Token::For => body.push(self.parse_for()?),
-                Token::Return => body.push(self.parse_return()?),
-                Token::Call => body.push(self.parse_function_call()?),
-                _ => {
-                    return Err(format!(
-                        "Unexpected token in function body: {:?}",
-                        self.current_token
-                    ));
-                }
-            }
-        }
+        // BREAK/CONTINUE cannot reach a loop outside the function
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        let enclosing_function = std::mem::replace(&mut self.in_function, true);
+        let body = self.parse_body(&[Token::End]);
+        self.loops = enclosing_loops;
+        self.in_function = enclosing_function;
+        let body = body?;
 
         if self.current_token != Token::End {
             return Err("Expected END to close FUNCTION".to_string());
@@ -1696,66 +2350,127 @@ impl Parser {
         Ok(Statement::FunctionDefinition {
             name: function_name,
             parameters,
-            body,
+            body: Rc::new(body),
         })
     }
 
     fn parse_function_call(&mut self) -> Result<Statement, String> {
         self.advance(); // Skip CALL
 
-        let function_name = if let Token::Identifier(name) = &self.current_token {
-            name.clone()
-        } else {
-            return Err("Expected function name after CALL".to_string());
-        };
-        self.advance();
+        let function_name = self.parse_function_name("Expected function name after CALL")?;
 
-        let mut arguments = Vec::new();
-        let mut result_name = None;
+        let (arguments, result_names) = self.parse_call_arguments()?;
 
-        // Parse optional arguments
-        if self.current_token == Token::LeftParen {
-            self.advance(); // Skip (
+        Ok(Statement::FunctionCall {
+            function_name,
+            arguments,
+            result_names,
+        })
+    }
 
-            while self.current_token != Token::RightParen && self.current_token != Token::EOF {
-                arguments.push(self.parse_expression()?);
+    /// A function's name, which may be qualified by the namespace an IMPORT
+    /// put it in: `geometry.AREA`. `missing` is the error when there is none.
+    fn parse_function_name(&mut self, missing: &str) -> Result<String, String> {
+        let Token::Identifier(name) = &self.current_token else {
+            return Err(missing.to_string());
+        };
+        let mut name = name.clone();
+        self.advance();
+        if self.current_token == Token::Dot {
+            self.advance(); // Skip .
+            let Token::Identifier(member) = &self.current_token else {
+                return Err(format!("Expected function name after {name}."));
+            };
+            name = format!("{name}.{member}");
+            self.advance();
+        }
+        Ok(name)
+    }
+
+    /// Parses the `(args)` and result names shared by CALL and CALL_DYNAMIC:
+    /// either a single result name or `INTO a, b` for several.
+    fn parse_call_arguments(&mut self) -> Result<(Vec<Expression>, Vec<String>), String> {
+        // Parse optional arguments
+        let arguments = if self.current_token == Token::LeftParen {
+            self.parse_argument_list()?
+        } else {
+            Vec::new()
+        };
+
+        // Check for result variable, or INTO a, b for several results
+        let mut result_names = Vec::new();
+        if self.current_token == Token::Into {
+            self.advance(); // Skip INTO
+            loop {
+                if let Token::Identifier(var_name) = &self.current_token {
+                    result_names.push(var_name.clone());
+                } else {
+                    return Err("Expected variable name after INTO".to_string());
+                }
+                self.advance();
+                if self.current_token != Token::Comma {
+                    break;
+                }
+                self.advance(); // Skip ,
+            }
+        } else if let Token::Identifier(var_name) = &self.current_token {
+            result_names.push(var_name.clone());
+            self.advance();
+        }
+
+        Ok((arguments, result_names))
+    }
+
+    /// Parses a parenthesized argument list, as CALL takes it.
+    fn parse_argument_list(&mut self) -> Result<Vec<Expression>, String> {
+        let mut arguments = Vec::new();
+        self.advance(); // Skip (
 
-                // Skip comma if present
-                if self.current_token == Token::Plus {
-                    // Using + as comma separator
+        while self.current_token != Token::RightParen && self.current_token != Token::EOF {
+            // A bare name passes an array or dictionary by reference
+            let by_reference = matches!(self.current_token, Token::Identifier(_))
+                && matches!(self.peek_token(), Token::Comma | Token::RightParen);
+            match &self.current_token {
+                Token::Identifier(name) if by_reference => {
+                    arguments.push(Expression::Recall(Symbol::intern(name)));
                     self.advance();
                 }
+                _ => arguments.push(self.parse_expression()?),
             }
 
-            if self.current_token != Token::RightParen {
-                return Err("Expected ) after function arguments".to_string());
+            // Skip comma if present
+            if self.current_token == Token::Comma {
+                self.advance();
             }
-            self.advance(); // Skip )
         }
 
-        // Check for result variable (INTO identifier)
-        if let Token::Identifier(var_name) = &self.current_token {
-            result_name = Some(var_name.clone());
-            self.advance();
+        if self.current_token != Token::RightParen {
+            return Err("Expected ) after function arguments".to_string());
         }
+        self.advance(); // Skip )
 
-        Ok(Statement::FunctionCall {
-            function_name,
-            arguments,
-            result_name,
-        })
+        Ok(arguments)
     }
 
     fn parse_return(&mut self) -> Result<Statement, String> {
+        if !self.in_function {
+            return Err(format!(
+                "RETURN outside of function at line {}",
+                self.current_line
+            ));
+        }
         self.advance(); // Skip RETURN
 
-        let value = if self.current_token == Token::EOF || self.current_token == Token::End {
-            None
-        } else {
-            Some(self.parse_expression()?)
-        };
+        let mut values = Vec::new();
+        if self.current_token != Token::EOF && self.current_token != Token::End {
+            values.push(self.parse_expression()?);
+            while self.current_token == Token::Comma {
+                self.advance(); // Skip ,
+                values.push(self.parse_expression()?);
+            }
+        }
 
-        Ok(Statement::Return { value })
+        Ok(Statement::Return { values })
     }
 
     fn parse_array_sort(&mut self) -> Result<Statement, String> {
@@ -1927,14 +2642,7 @@ impl Parser {
         };
         self.advance();
 
-        let key = if let Token::StringLiteral(k) = &self.current_token {
-            k.clone()
-        } else if let Token::Identifier(k) = &self.current_token {
-            k.clone()
-        } else {
-            return Err("Expected key for PUT".to_string());
-        };
-        self.advance();
+        let key = self.parse_dict_key("PUT")?;
 
         let value = self.parse_expression()?;
 
@@ -1955,14 +2663,7 @@ impl Parser {
         };
         self.advance();
 
-        let key = if let Token::StringLiteral(k) = &self.current_token {
-            k.clone()
-        } else if let Token::Identifier(k) = &self.current_token {
-            k.clone()
-        } else {
-            return Err("Expected key for FETCH".to_string());
-        };
-        self.advance();
+        let key = self.parse_dict_key("FETCH")?;
 
         let result_name = if let Token::Identifier(name) = &self.current_token {
             name.clone()
@@ -1978,6 +2679,28 @@ impl Parser {
         })
     }
 
+    /// Parses a dictionary key. A string literal or bare identifier is used
+    /// verbatim as the key; `RECALL name` uses the value of `name` instead and
+    /// is stored as a `${name}` reference resolved at runtime.
+    fn parse_dict_key(&mut self, keyword: &str) -> Result<String, String> {
+        let key = match &self.current_token {
+            Token::StringLiteral(k) | Token::Identifier(k) => k.clone(),
+            Token::Recall => {
+                self.advance(); // Skip RECALL
+                if let Token::Identifier(var) = &self.current_token {
+                    format!("${{{var}}}")
+                } else {
+                    return Err(format!(
+                        "Expected variable name after RECALL in {keyword} key"
+                    ));
+                }
+            }
+            _ => return Err(format!("Expected key for {keyword}")),
+        };
+        self.advance();
+        Ok(key)
+    }
+
     fn parse_dict_keys(&mut self) -> Result<Statement, String> {
         self.advance(); // Skip KEYS
 
@@ -2034,14 +2757,7 @@ impl Parser {
         };
         self.advance();
 
-        let key = if let Token::StringLiteral(k) = &self.current_token {
-            k.clone()
-        } else if let Token::Identifier(k) = &self.current_token {
-            k.clone()
-        } else {
-            return Err("Expected key for DELETE".to_string());
-        };
-        self.advance();
+        let key = self.parse_dict_key("DELETE")?;
 
         Ok(Statement::DictDelete { dict_name, key })
     }
@@ -2358,33 +3074,47 @@ impl Parser {
     }
 
     fn parse_sleep(&mut self) -> Result<Statement, String> {
-        self.advance(); // Skip SLEEP
+        let in_seconds = self.current_token == Token::SleepSec;
+        self.advance(); // Skip SLEEP or SLEEP_SEC
 
-        let milliseconds = self.parse_expression()?;
+        let duration = self.parse_expression()?;
 
-        Ok(Statement::Sleep { milliseconds })
+        Ok(if in_seconds {
+            Statement::SleepSec { seconds: duration }
+        } else {
+            Statement::Sleep {
+                milliseconds: duration,
+            }
+        })
     }
 
     fn parse_input(&mut self) -> Result<Statement, String> {
-        self.advance(); // Skip INPUT
+        let keyword = self.current_token.to_string();
+        let mode = match self.current_token {
+            Token::InputStr => InputMode::Text,
+            Token::InputNum => InputMode::Number,
+            _ => InputMode::Auto,
+        };
+        self.advance(); // Skip INPUT, INPUT_STR or INPUT_NUM
 
         let prompt = if let Token::StringLiteral(p) = &self.current_token {
             p.clone()
         } else {
-            return Err("Expected prompt string after INPUT".to_string());
+            return Err(format!("Expected prompt string after {keyword}"));
         };
         self.advance();
 
         let result_name = if let Token::Identifier(name) = &self.current_token {
             name.clone()
         } else {
-            return Err("Expected result variable name for INPUT".to_string());
+            return Err(format!("Expected result variable name for {keyword}"));
         };
         self.advance();
 
         Ok(Statement::Input {
             prompt,
             result_name,
+            mode,
         })
     }
 
@@ -2430,9 +3160,19 @@ impl Parser {
         };
         self.advance();
 
+        // Optional status variable: `PARSE source result status`
+        let status_name = if let Token::Identifier(name) = &self.current_token {
+            let name = name.clone();
+            self.advance();
+            Some(name)
+        } else {
+            None
+        };
+
         Ok(Statement::ParseNumber {
             source,
             result_name,
+            status_name,
         })
     }
 
@@ -2607,4 +3347,1297 @@ impl Parser {
             result_array,
         })
     }
+
+    fn parse_dict_size(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip DICT_SIZE
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result variable name after DICT_SIZE".to_string());
+        };
+        self.advance();
+
+        let dict_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected dictionary name for DICT_SIZE".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::DictSize {
+            result_name,
+            dict_name,
+        })
+    }
+
+    fn parse_record(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip RECORD
+        let name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected record name after RECORD".to_string());
+        };
+        self.advance();
+
+        // Fields are name/value pairs: a string literal, the name of another
+        // record to nest, or a numeric expression
+        let mut fields = Vec::new();
+        while let Token::Identifier(field) = &self.current_token {
+            let field = field.clone();
+            self.advance();
+
+            let value = match &self.current_token {
+                Token::StringLiteral(s) => {
+                    let text = s.clone();
+                    self.advance();
+                    RecordField::Text(text)
+                }
+                Token::Identifier(record) => {
+                    let record = record.clone();
+                    self.advance();
+                    RecordField::Record(record)
+                }
+                Token::EOF => {
+                    return Err(format!("Expected value for field '{field}' in RECORD"));
+                }
+                _ => RecordField::Expression(self.parse_expression()?),
+            };
+            fields.push((field, value));
+        }
+
+        if fields.is_empty() {
+            return Err("Expected at least one field for RECORD".to_string());
+        }
+
+        Ok(Statement::RecordCreate { name, fields })
+    }
+
+    fn parse_field(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FIELD
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result variable name after FIELD".to_string());
+        };
+        self.advance();
+
+        let record_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected record name for FIELD".to_string());
+        };
+        self.advance();
+
+        let field = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected field name for FIELD".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::RecordField {
+            result_name,
+            record_name,
+            field,
+        })
+    }
+
+    fn parse_scope(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip SCOPE
+        let body = self.parse_block("SCOPE")?;
+
+        Ok(Statement::Scope { body })
+    }
+
+    fn parse_histogram(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip HISTOGRAM
+        let array_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected array name after HISTOGRAM".to_string());
+        };
+        self.advance();
+
+        // Optional maximum bar width
+        let width = if matches!(
+            self.current_token,
+            Token::Number(_) | Token::Recall | Token::LeftParen
+        ) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        Ok(Statement::Histogram { array_name, width })
+    }
+
+    fn parse_print_table(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip PRINT_TABLE
+        let dict_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected dictionary name after PRINT_TABLE".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::PrintTable { dict_name })
+    }
+
+    fn parse_fn_ref(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FN_REF
+        let name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected variable name after FN_REF".to_string());
+        };
+        self.advance();
+
+        let function_name = self.parse_function_name("Expected function name for FN_REF")?;
+
+        Ok(Statement::FunctionRef {
+            name,
+            function_name,
+        })
+    }
+
+    fn parse_dynamic_call(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip CALL_DYNAMIC
+        let ref_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected function reference after CALL_DYNAMIC".to_string());
+        };
+        self.advance();
+
+        let (arguments, result_names) = self.parse_call_arguments()?;
+
+        Ok(Statement::DynamicCall {
+            ref_name,
+            arguments,
+            result_names,
+        })
+    }
+
+    fn parse_assert_near(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip ASSERT_NEAR
+
+        let left = self.parse_expression()?;
+        if self.current_token == Token::Comma {
+            self.advance();
+        }
+        let right = self.parse_expression()?;
+        if self.current_token == Token::Comma {
+            self.advance();
+        }
+        let tolerance = self.parse_expression()?;
+
+        let message = if let Token::StringLiteral(msg) = &self.current_token {
+            let msg = msg.clone();
+            self.advance();
+            Some(msg)
+        } else {
+            None
+        };
+
+        Ok(Statement::AssertNear {
+            left,
+            right,
+            tolerance,
+            message,
+        })
+    }
+
+    fn parse_unless(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip UNLESS
+
+        let condition = self.parse_expression()?;
+
+        if self.current_token != Token::Do {
+            return Err("Expected DO after UNLESS condition".to_string());
+        }
+        self.advance(); // Skip DO
+
+        let body = self.parse_block("UNLESS")?;
+
+        Ok(Statement::Unless { condition, body })
+    }
+
+    fn parse_read_lines(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip READ_LINES
+
+        let filename = if let Token::StringLiteral(f) = &self.current_token {
+            f.clone()
+        } else {
+            return Err("Expected filename string after READ_LINES".to_string());
+        };
+        self.advance();
+
+        let result_array = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result array name for READ_LINES".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::ReadLines {
+            filename,
+            result_array,
+        })
+    }
+
+    fn parse_write_lines(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip WRITE_LINES
+
+        let filename = if let Token::StringLiteral(f) = &self.current_token {
+            f.clone()
+        } else {
+            return Err("Expected filename string after WRITE_LINES".to_string());
+        };
+        self.advance();
+
+        let array_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected array name for WRITE_LINES".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::WriteLines {
+            filename,
+            array_name,
+        })
+    }
+
+    fn parse_extend(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip EXTEND
+
+        let dest = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected destination array name after EXTEND".to_string());
+        };
+        self.advance();
+
+        let src = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected source array name for EXTEND".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::Extend { dest, src })
+    }
+
+    fn parse_list_dir(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip LIST_DIR
+
+        let path = if let Token::StringLiteral(p) = &self.current_token {
+            p.clone()
+        } else {
+            return Err("Expected directory path string after LIST_DIR".to_string());
+        };
+        self.advance();
+
+        let result_array = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result array name for LIST_DIR".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::ListDir { path, result_array })
+    }
+
+    fn parse_file_size(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FILE_SIZE
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result variable name after FILE_SIZE".to_string());
+        };
+        self.advance();
+
+        let filename = if let Token::StringLiteral(f) = &self.current_token {
+            f.clone()
+        } else {
+            return Err("Expected filename string for FILE_SIZE".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::FileSize {
+            result_name,
+            filename,
+        })
+    }
+
+    fn parse_delete_file(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip DELETE_FILE
+
+        let filename = if let Token::StringLiteral(f) = &self.current_token {
+            f.clone()
+        } else {
+            return Err("Expected filename string after DELETE_FILE".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::DeleteFile { filename })
+    }
+
+    fn parse_rename_file(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip RENAME_FILE
+
+        let from = if let Token::StringLiteral(f) = &self.current_token {
+            f.clone()
+        } else {
+            return Err("Expected source filename string after RENAME_FILE".to_string());
+        };
+        self.advance();
+
+        let to = if let Token::StringLiteral(f) = &self.current_token {
+            f.clone()
+        } else {
+            return Err("Expected destination filename string for RENAME_FILE".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::RenameFile { from, to })
+    }
+
+    fn parse_format_thousands(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FORMAT_THOUSANDS
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result variable name after FORMAT_THOUSANDS".to_string());
+        };
+        self.advance();
+
+        let value = self.parse_expression()?;
+
+        Ok(Statement::FormatThousands { result_name, value })
+    }
+
+    fn parse_format_sci(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FORMAT_SCI
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result variable name after FORMAT_SCI".to_string());
+        };
+        self.advance();
+
+        let value = self.parse_expression()?;
+        if self.current_token == Token::Comma {
+            self.advance();
+        }
+        let places = self.parse_expression()?;
+
+        Ok(Statement::FormatSci {
+            result_name,
+            value,
+            places,
+        })
+    }
+
+    fn parse_equality_check(&mut self) -> Result<Statement, String> {
+        let keyword = self.current_token.clone();
+        self.advance(); // Skip ARRAY_EQ / DICT_EQ / ARRAY_CMP
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err(format!("Expected result variable name after {keyword}"));
+        };
+        self.advance();
+
+        let first = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err(format!("Expected first name for {keyword}"));
+        };
+        self.advance();
+
+        let second = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err(format!("Expected second name for {keyword}"));
+        };
+        self.advance();
+
+        match keyword {
+            Token::ArrayEq => Ok(Statement::ArrayEq {
+                result_name,
+                first,
+                second,
+            }),
+            Token::ArrayCmp => Ok(Statement::ArrayCmp {
+                result_name,
+                first,
+                second,
+            }),
+            _ => Ok(Statement::DictEq {
+                result_name,
+                first,
+                second,
+            }),
+        }
+    }
+
+    fn parse_cond(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip COND
+
+        let mut branches = Vec::new();
+        self.skip_terminators();
+        while self.current_token == Token::When {
+            self.advance(); // Skip WHEN
+            let guard = self.parse_expression()?;
+
+            if self.current_token != Token::Do {
+                return Err("Expected DO after WHEN condition".to_string());
+            }
+            self.advance(); // Skip DO
+
+            let body = self.parse_body(&[Token::When, Token::Else, Token::End])?;
+            branches.push((guard, body));
+        }
+
+        if branches.is_empty() {
+            return Err("Expected WHEN after COND".to_string());
+        }
+
+        let else_body = if self.current_token == Token::Else {
+            self.advance(); // Skip ELSE
+            Some(self.parse_body(&[Token::End])?)
+        } else {
+            None
+        };
+
+        if self.current_token != Token::End {
+            return Err("Expected END to close COND".to_string());
+        }
+        self.advance(); // Skip END
+
+        Ok(Statement::Cond {
+            branches,
+            else_body,
+        })
+    }
+
+    fn parse_for_range(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FOR_RANGE
+
+        let variable = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected variable name after FOR_RANGE".to_string());
+        };
+        self.advance();
+
+        // Commas between the bounds are optional, but needed before a negative step
+        let start = self.parse_expression()?;
+        if self.current_token == Token::Comma {
+            self.advance();
+        }
+        let end = self.parse_expression()?;
+        if self.current_token == Token::Comma {
+            self.advance();
+        }
+        let step = if self.current_token == Token::Do {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+
+        if self.current_token != Token::Do {
+            return Err("Expected DO after FOR_RANGE parameters".to_string());
+        }
+        self.advance(); // Skip DO
+
+        let (body, label) = self.parse_loop_body()?;
+
+        if self.current_token != Token::End {
+            return Err("Expected END to close FOR_RANGE".to_string());
+        }
+        self.advance(); // Skip END
+
+        Ok(Statement::ForRange {
+            variable,
+            start,
+            end,
+            step,
+            body,
+            label,
+        })
+    }
+
+    fn parse_enumerate(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip ENUMERATE
+
+        let array_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected array name after ENUMERATE".to_string());
+        };
+        self.advance();
+
+        let index_var = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected index variable name for ENUMERATE".to_string());
+        };
+        self.advance();
+
+        let value_var = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected value variable name for ENUMERATE".to_string());
+        };
+        self.advance();
+
+        if self.current_token != Token::Do {
+            return Err("Expected DO after ENUMERATE variables".to_string());
+        }
+        self.advance(); // Skip DO
+
+        let (body, label) = self.parse_loop_body()?;
+
+        if self.current_token != Token::End {
+            return Err("Expected END to close ENUMERATE".to_string());
+        }
+        self.advance(); // Skip END
+
+        Ok(Statement::Enumerate {
+            array_name,
+            index_var,
+            value_var,
+            body,
+            label,
+        })
+    }
+
+    fn parse_slice_str(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip SLICE_STR
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result name after SLICE_STR".to_string());
+        };
+        self.advance();
+
+        let source = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected string variable for SLICE_STR".to_string());
+        };
+        self.advance();
+
+        // A comma is needed before a negative end (`SLICE_STR r s 0, -1`)
+        let start = self.parse_expression()?;
+        if self.current_token == Token::Comma {
+            self.advance();
+        }
+        let end = self.parse_expression()?;
+
+        Ok(Statement::SliceStr {
+            result_name,
+            source,
+            start,
+            end,
+        })
+    }
+
+    fn parse_includes(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip INCLUDES
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result name after INCLUDES".to_string());
+        };
+        self.advance();
+
+        let array_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected array name for INCLUDES".to_string());
+        };
+        self.advance();
+
+        let value = if let Token::StringLiteral(text) = &self.current_token {
+            let text = text.clone();
+            self.advance();
+            Operand::Text(text)
+        } else {
+            Operand::Expression(self.parse_expression()?)
+        };
+
+        Ok(Statement::Includes {
+            result_name,
+            array_name,
+            value,
+        })
+    }
+
+    fn parse_dict_transform(&mut self) -> Result<Statement, String> {
+        let keyword = self.current_token.clone();
+        self.advance(); // Skip MAP_DICT / FILTER_DICT
+
+        let dict_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err(format!("Expected dictionary name after {keyword}"));
+        };
+        self.advance();
+
+        let expression = self.parse_expression()?;
+
+        let result_dict = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err(format!("Expected result dictionary name for {keyword}"));
+        };
+        self.advance();
+
+        if keyword == Token::MapDict {
+            Ok(Statement::MapDict {
+                dict_name,
+                expression,
+                result_dict,
+            })
+        } else {
+            Ok(Statement::FilterDict {
+                dict_name,
+                condition: expression,
+                result_dict,
+            })
+        }
+    }
+
+    fn parse_count_str(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip COUNT_STR
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result name after COUNT_STR".to_string());
+        };
+        self.advance();
+
+        let source = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected string variable for COUNT_STR".to_string());
+        };
+        self.advance();
+
+        let pattern = if let Token::StringLiteral(p) = &self.current_token {
+            p.clone()
+        } else {
+            return Err("Expected pattern string for COUNT_STR".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::CountStr {
+            result_name,
+            source,
+            pattern,
+        })
+    }
+
+    fn parse_parse_radix(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip PARSE_RADIX
+
+        // A name is kept as `${name}`, so it is not mistaken for literal text
+        let source = match &self.current_token {
+            Token::Identifier(name) => format!("${{{name}}}"),
+            Token::StringLiteral(s) => s.clone(),
+            _ => return Err("Expected source for PARSE_RADIX".to_string()),
+        };
+        self.advance();
+
+        let radix = self.parse_expression()?;
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result variable name for PARSE_RADIX".to_string());
+        };
+        self.advance();
+
+        // Optional status variable, as in PARSE
+        let status_name = if let Token::Identifier(name) = &self.current_token {
+            let name = name.clone();
+            self.advance();
+            Some(name)
+        } else {
+            None
+        };
+
+        Ok(Statement::ParseRadix {
+            source,
+            radix,
+            result_name,
+            status_name,
+        })
+    }
+
+    fn parse_to_radix(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip TO_RADIX
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result name after TO_RADIX".to_string());
+        };
+        self.advance();
+
+        let value = self.parse_expression()?;
+        if self.current_token == Token::Comma {
+            self.advance();
+        }
+        let radix = self.parse_expression()?;
+
+        Ok(Statement::ToRadix {
+            result_name,
+            value,
+            radix,
+        })
+    }
+
+    fn parse_format_date(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FORMAT_DATE
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result name after FORMAT_DATE".to_string());
+        };
+        self.advance();
+
+        let epoch = self.parse_expression()?;
+
+        let pattern = if let Token::StringLiteral(p) = &self.current_token {
+            p.clone()
+        } else {
+            return Err("Expected pattern string for FORMAT_DATE".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::FormatDate {
+            result_name,
+            epoch,
+            pattern,
+        })
+    }
+
+    fn parse_dict_get_or(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip DICT_GET_OR
+
+        let dict_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected dictionary name after DICT_GET_OR".to_string());
+        };
+        self.advance();
+
+        let key = self.parse_dict_key("DICT_GET_OR")?;
+
+        let default = self.parse_expression()?;
+
+        let result_name = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result variable name for DICT_GET_OR".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::DictGetOr {
+            dict_name,
+            key,
+            default,
+            result_name,
+        })
+    }
+
+    fn parse_exit(&mut self) -> Result<Statement, String> {
+        let line = self.current_line;
+        self.advance(); // Skip EXIT
+
+        // The code is optional, so only an expression on the same line belongs to EXIT
+        let code = if !matches!(self.current_token, Token::EOF | Token::Terminator)
+            && self.current_line == line
+        {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        Ok(Statement::Exit { code })
+    }
+
+    fn parse_zip_with(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip ZIP_WITH
+
+        let a = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected first array name after ZIP_WITH".to_string());
+        };
+        self.advance();
+
+        let b = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected second array name for ZIP_WITH".to_string());
+        };
+        self.advance();
+
+        let expression = self.parse_expression()?;
+
+        let result_array = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result array name for ZIP_WITH".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::ZipWith {
+            a,
+            b,
+            expression,
+            result_array,
+        })
+    }
+
+    fn parse_collect(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip COLLECT
+
+        let result_array = if let Token::Identifier(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected result array name after COLLECT".to_string());
+        };
+        self.advance();
+
+        if self.current_token != Token::From {
+            return Err("Expected FROM in COLLECT".to_string());
+        }
+        self.advance();
+        let start = self.parse_expression()?;
+
+        if self.current_token != Token::To {
+            return Err("Expected TO in COLLECT".to_string());
+        }
+        self.advance();
+        let end = self.parse_expression()?;
+
+        let step = if self.current_token == Token::Step {
+            self.advance();
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        let condition = if self.current_token == Token::Where {
+            self.advance();
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        if self.current_token != Token::Select {
+            return Err("Expected SELECT in COLLECT".to_string());
+        }
+        self.advance();
+        let expression = self.parse_expression()?;
+
+        Ok(Statement::Collect {
+            result_array,
+            start,
+            end,
+            step,
+            condition,
+            expression,
+        })
+    }
+
+    fn parse_use(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip USE
+
+        let filename = if let Token::StringLiteral(name) = &self.current_token {
+            name.clone()
+        } else {
+            return Err("Expected filename string after USE".to_string());
+        };
+        self.advance();
+
+        Ok(Statement::Use { filename })
+    }
+
+    fn parse_log(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip LOG
+        let level = self.parse_log_level()?;
+
+        let mut items = Vec::new();
+        loop {
+            match &self.current_token {
+                Token::StringLiteral(s) => items.push(s.clone()),
+                Token::Identifier(id) => items.push(format!("${{{id}}}")),
+                _ => break,
+            }
+            self.advance();
+        }
+        if items.is_empty() {
+            return Err(format!("Expected message after LOG {level}"));
+        }
+
+        Ok(Statement::Log { level, items })
+    }
+
+    fn parse_log_level(&mut self) -> Result<LogLevel, String> {
+        let level = match &self.current_token {
+            Token::Identifier(name) => match name.as_str() {
+                "DEBUG" => LogLevel::Debug,
+                "INFO" => LogLevel::Info,
+                "WARN" => LogLevel::Warn,
+                "ERROR" => LogLevel::Error,
+                _ => {
+                    return Err(format!(
+                        "Unknown log level '{name}'; expected DEBUG, INFO, WARN or ERROR"
+                    ));
+                }
+            },
+            other => return Err(format!("Expected log level, found {other}")),
+        };
+        self.advance();
+        Ok(level)
+    }
+
+    fn parse_format(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip FORMAT
+
+        let Token::Identifier(result_name) = &self.current_token else {
+            return Err("Expected result variable name after FORMAT".to_string());
+        };
+        let result_name = result_name.clone();
+        self.advance();
+
+        let Token::StringLiteral(template) = &self.current_token else {
+            return Err(format!(
+                "Expected template string after FORMAT {result_name}"
+            ));
+        };
+        let template = template.clone();
+        self.advance();
+
+        // Arguments are written as for COMBINE
+        let mut arguments = Vec::new();
+        loop {
+            match &self.current_token {
+                Token::StringLiteral(s) => {
+                    arguments.push(CombinePart::Literal(s.clone()));
+                    self.advance();
+                }
+                Token::Identifier(id) => {
+                    arguments.push(CombinePart::Variable(id.clone()));
+                    self.advance();
+                }
+                Token::LeftParen | Token::Recall | Token::Number(_) | Token::Minus => {
+                    arguments.push(CombinePart::Expression(self.parse_expression()?));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(Statement::Format {
+            result_name,
+            template,
+            arguments,
+        })
+    }
+
+    fn parse_container_conversion(&mut self) -> Result<Statement, String> {
+        let keyword = self.current_token.clone();
+        self.advance(); // Skip ARRAY_TO_DICT / DICT_TO_ARRAY
+
+        let Token::Identifier(source) = &self.current_token else {
+            return Err(format!("Expected source name after {keyword}"));
+        };
+        let source = source.clone();
+        self.advance();
+
+        let Token::Identifier(target) = &self.current_token else {
+            return Err(format!("Expected target name after {keyword} {source}"));
+        };
+        let target = target.clone();
+        self.advance();
+
+        Ok(if keyword == Token::ArrayToDict {
+            Statement::ArrayToDict {
+                array_name: source,
+                dict_name: target,
+            }
+        } else {
+            Statement::DictToArray {
+                dict_name: source,
+                array_name: target,
+            }
+        })
+    }
+
+    fn parse_require_keys(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip REQUIRE_KEYS
+
+        let Token::Identifier(dict_name) = &self.current_token else {
+            return Err("Expected dictionary name after REQUIRE_KEYS".to_string());
+        };
+        let dict_name = dict_name.clone();
+        self.advance();
+
+        let mut keys = Vec::new();
+        while matches!(
+            self.current_token,
+            Token::StringLiteral(_) | Token::Identifier(_) | Token::Recall
+        ) {
+            keys.push(self.parse_dict_key("REQUIRE_KEYS")?);
+        }
+        if keys.is_empty() {
+            return Err(format!("Expected keys after REQUIRE_KEYS {dict_name}"));
+        }
+
+        Ok(Statement::RequireKeys { dict_name, keys })
+    }
+
+    /// Parses `source.method(...).method(...) INTO result`, one step per
+    /// `.method(...)`.
+    fn parse_array_chain(&mut self) -> Result<Statement, String> {
+        let Token::Identifier(source) = &self.current_token else {
+            return Err("Expected array name".to_string());
+        };
+        let source = source.clone();
+        self.advance();
+
+        let mut calls = Vec::new();
+        while self.current_token == Token::Dot {
+            self.advance(); // Skip .
+            let Token::Identifier(method) = &self.current_token else {
+                return Err("Expected method name after .".to_string());
+            };
+            let method = method.clone();
+            self.advance();
+
+            if self.current_token != Token::LeftParen {
+                return Err(format!("Expected ( after .{method}"));
+            }
+            self.advance();
+            let argument = match method.as_str() {
+                "take" | "drop" | "filter" | "map" => Some(self.parse_expression()?),
+                "sort" | "sort_desc" | "reverse" | "unique" | "shuffle" => None,
+                _ => {
+                    return Err(format!(
+                        "Unknown array method .{method}(); chainable methods are sort, \
+                         sort_desc, reverse, unique, shuffle, take, drop, filter and map"
+                    ));
+                }
+            };
+            if self.current_token != Token::RightParen {
+                return Err(format!("Expected ) to close .{method}("));
+            }
+            self.advance();
+            calls.push((method, argument));
+        }
+
+        if self.current_token != Token::Into {
+            return Err(format!("Expected INTO after {source}'s method chain"));
+        }
+        self.advance();
+        let Token::Identifier(result_array) = &self.current_token else {
+            return Err("Expected result array name after INTO".to_string());
+        };
+        let result_array = result_array.clone();
+        self.advance();
+
+        // Each call becomes the matching statement on the working copy
+        let steps = calls
+            .into_iter()
+            .map(|(method, argument)| {
+                let array_name = result_array.clone();
+                let result = result_array.clone();
+                match (method.as_str(), argument) {
+                    ("take", Some(count)) => Statement::Take {
+                        array_name,
+                        count,
+                        result_array: result,
+                    },
+                    ("drop", Some(count)) => Statement::Drop {
+                        array_name,
+                        count,
+                        result_array: result,
+                    },
+                    ("filter", Some(condition)) => Statement::ArrayFilter {
+                        array_name,
+                        condition,
+                        result_array: result,
+                    },
+                    ("map", Some(expression)) => Statement::ArrayMap {
+                        array_name,
+                        expression,
+                        result_array: result,
+                    },
+                    ("unique", _) => Statement::Unique {
+                        array_name,
+                        result_array: result,
+                    },
+                    ("reverse", _) => Statement::ArrayReverse { array_name },
+                    ("shuffle", _) => Statement::Shuffle { array_name },
+                    ("sort", _) => Statement::ArraySort {
+                        array_name,
+                        ascending: true,
+                    },
+                    // sort_desc; other names were rejected above
+                    _ => Statement::ArraySort {
+                        array_name,
+                        ascending: false,
+                    },
+                }
+            })
+            .collect();
+
+        Ok(Statement::ArrayChain {
+            source,
+            result_array,
+            steps,
+        })
+    }
+
+    fn parse_parse_json(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip PARSE_JSON
+
+        let Token::Identifier(result_name) = &self.current_token else {
+            return Err("Expected result name after PARSE_JSON".to_string());
+        };
+        let result_name = result_name.clone();
+        self.advance();
+
+        let source = match &self.current_token {
+            Token::StringLiteral(text) => text.clone(),
+            Token::Identifier(var) => format!("${{{var}}}"),
+            _ => return Err(format!("Expected JSON text after PARSE_JSON {result_name}")),
+        };
+        self.advance();
+
+        Ok(Statement::ParseJson {
+            result_name,
+            source,
+        })
+    }
+
+    fn parse_serialize_json(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip SERIALIZE_JSON
+
+        let Token::Identifier(result_name) = &self.current_token else {
+            return Err("Expected result name after SERIALIZE_JSON".to_string());
+        };
+        let result_name = result_name.clone();
+        self.advance();
+
+        let Token::Identifier(source) = &self.current_token else {
+            return Err(format!(
+                "Expected a name to serialize after SERIALIZE_JSON {result_name}"
+            ));
+        };
+        let source = source.clone();
+        self.advance();
+
+        Ok(Statement::SerializeJson {
+            result_name,
+            source,
+        })
+    }
+
+    fn parse_sum_numeric(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip SUM_NUMERIC
+
+        let Token::Identifier(array_name) = &self.current_token else {
+            return Err("Expected array name after SUM_NUMERIC".to_string());
+        };
+        let array_name = array_name.clone();
+        self.advance();
+
+        let Token::Identifier(result_name) = &self.current_token else {
+            return Err("Expected result variable name for SUM_NUMERIC".to_string());
+        };
+        let result_name = result_name.clone();
+        self.advance();
+
+        // STRICT is the default
+        let lenient = match &self.current_token {
+            Token::Identifier(mode) if mode == "STRICT" || mode == "LENIENT" => {
+                let lenient = mode == "LENIENT";
+                self.advance();
+                lenient
+            }
+            _ => false,
+        };
+
+        Ok(Statement::SumNumeric {
+            array_name,
+            result_name,
+            lenient,
+        })
+    }
+
+    fn parse_array_extreme(&mut self) -> Result<Statement, String> {
+        let keyword = self.current_token.clone();
+        self.advance(); // Skip MIN_OF, MAX_OF, MIN_OF_OR or MAX_OF_OR
+
+        let Token::Identifier(array_name) = &self.current_token else {
+            return Err(format!("Expected array name after {keyword}"));
+        };
+        let array_name = array_name.clone();
+        self.advance();
+
+        let default = if matches!(keyword, Token::MinOfOr | Token::MaxOfOr) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        let Token::Identifier(result_name) = &self.current_token else {
+            return Err(format!("Expected result variable name for {keyword}"));
+        };
+        let result_name = result_name.clone();
+        self.advance();
+
+        Ok(Statement::ArrayExtreme {
+            array_name,
+            result_name,
+            max: matches!(keyword, Token::MaxOfOp | Token::MaxOfOr),
+            default,
+        })
+    }
+
+    fn parse_stats(&mut self) -> Result<Statement, String> {
+        self.advance(); // Skip STATS
+
+        let Token::Identifier(array_name) = &self.current_token else {
+            return Err("Expected array name after STATS".to_string());
+        };
+        let array_name = array_name.clone();
+        self.advance();
+
+        let Token::Identifier(prefix) = &self.current_token else {
+            return Err(format!(
+                "Expected a prefix for the results of STATS {array_name}"
+            ));
+        };
+        let prefix = prefix.clone();
+        self.advance();
+
+        Ok(Statement::Stats { array_name, prefix })
+    }
 }